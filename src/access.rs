@@ -0,0 +1,262 @@
+// Structured access logs: one record per connection or HTTP request
+
+use anyhow::Result;
+use chrono::{Local, SecondsFormat};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use crate::cfg::Config;
+use crate::logfile::LogFile;
+use rsb::prelude::*;
+
+/// The top-level `log_format` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Default, // Decorated text lines
+    Json,
+    Logfmt,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<LogFormat> {
+        match value {
+            "default" => Ok(LogFormat::Default),
+            "json" => Ok(LogFormat::Json),
+            "logfmt" => Ok(LogFormat::Logfmt),
+            other => Err(anyhow::anyhow!(
+                "Unknown log_format '{}' (expected default, json or logfmt)",
+                other
+            )),
+        }
+    }
+
+    /// Structured formats replace the per-connection and per-request text lines.
+    pub fn is_structured(self) -> bool {
+        self != LogFormat::Default
+    }
+}
+
+/// Why a connection or request failed, as reported in the `error` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    BadRequest, // Malformed HTTP request
+    NoRoute,    // No virtual host for the Host header
+    NoBackend,  // Every backend is down
+    Connect,    // Backend connection failed
+    Timeout,
+    Tls,        // TLS handshake or ClientHello failed
+    Killed,     // Closed via the admin API
+    Forbidden,  // Refused by the route's allow/deny lists
+    TooLarge,   // Request body over the route's max_request_body
+    Overloaded, // UDP route at its max_connections session limit
+    Io,         // Anything else while relaying
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::BadRequest => "bad_request",
+            ErrorKind::NoRoute => "no_route",
+            ErrorKind::NoBackend => "no_backend",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Killed => "killed",
+            ErrorKind::Forbidden => "forbidden",
+            ErrorKind::TooLarge => "too_large",
+            ErrorKind::Overloaded => "overloaded",
+            ErrorKind::Io => "io",
+        }
+    }
+}
+
+/// An error tagged with its kind. Displays exactly like the wrapped error.
+#[derive(Debug)]
+struct Classified {
+    kind: ErrorKind,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Classified {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Tag an error so `error_kind` can report it, even under added context.
+pub fn classify(kind: ErrorKind, error: impl Into<anyhow::Error>) -> anyhow::Error {
+    anyhow::Error::new(Classified { kind, error: error.into() })
+}
+
+/// The kind an error was tagged with, or `Io` for untagged errors.
+pub fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<Classified>())
+        .map(|classified| classified.kind)
+        .unwrap_or(ErrorKind::Io)
+}
+
+/// One access log line. HTTP fields stay empty for plain TCP, TLS passthrough
+/// and UDP records.
+#[derive(Debug, Default, Serialize)]
+pub struct AccessRecord {
+    pub timestamp: String,
+    pub route: String,
+    pub client: String,
+    pub target: Option<String>,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub bytes_in: u64,  // Read from the client
+    pub bytes_out: u64, // Written to the client
+    pub duration_ms: u64,
+    pub retries: u32,
+    pub error: Option<ErrorKind>,
+}
+
+impl AccessRecord {
+    pub fn new(route: &str, client: &str) -> AccessRecord {
+        AccessRecord {
+            route: route.to_string(),
+            client: client.to_string(),
+            ..AccessRecord::default()
+        }
+    }
+
+    /// Stamp the record with the current time and write it to the route's access
+    /// log file, or print it on stdout in a structured format.
+    pub fn emit(mut self, format: LogFormat, duration: Duration) {
+        self.timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        self.duration_ms = duration.as_millis() as u64;
+        if let Some(file) = file_for(&self.route) {
+            // Files always get records; the default format writes them as logfmt
+            let line = match format {
+                LogFormat::Json => serde_json::to_string(&self).unwrap_or_default(),
+                _ => self.to_logfmt(),
+            };
+            file.write_line(&line);
+            return;
+        }
+        match format {
+            LogFormat::Default => {}
+            LogFormat::Json => {
+                if let Ok(line) = serde_json::to_string(&self) {
+                    echo!("{}", line);
+                }
+            }
+            LogFormat::Logfmt => echo!("{}", self.to_logfmt()),
+        }
+    }
+
+    /// `key=value` pairs in field order; empty fields are left out.
+    pub fn to_logfmt(&self) -> String {
+        let optional = |value: &Option<String>| value.as_deref().map(logfmt_value);
+        let fields = [
+            ("timestamp", Some(self.timestamp.clone())),
+            ("route", Some(logfmt_value(&self.route))),
+            ("client", Some(logfmt_value(&self.client))),
+            ("target", optional(&self.target)),
+            ("method", optional(&self.method)),
+            ("path", optional(&self.path)),
+            ("status", self.status.map(|s| s.to_string())),
+            ("bytes_in", Some(self.bytes_in.to_string())),
+            ("bytes_out", Some(self.bytes_out.to_string())),
+            ("duration_ms", Some(self.duration_ms.to_string())),
+            ("retries", Some(self.retries.to_string())),
+            ("error", self.error.map(|e| e.as_str().to_string())),
+        ];
+        fields
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}={}", key, value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=' || c == '\\') {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Access log files by route name. Routes without an `access_log` of their own,
+/// and listeners shared by several routes, use the top-level one.
+#[derive(Debug, Default)]
+pub struct AccessFiles {
+    default: Option<Arc<LogFile>>,
+    routes: HashMap<String, Arc<LogFile>>,
+}
+
+impl AccessFiles {
+    /// Open every access log the config names.
+    pub fn open(config: &Config) -> Result<AccessFiles> {
+        let paths = config.access_log.iter().chain(config.routes.iter().filter_map(|r| r.access_log.as_ref()));
+        for path in paths {
+            if config.error_log.as_ref() == Some(path) {
+                return Err(anyhow::anyhow!("access_log and error_log must be different files ({})", path));
+            }
+        }
+
+        let mut files = AccessFiles {
+            default: config.access_log.as_deref().map(LogFile::open).transpose()?,
+            routes: HashMap::new(),
+        };
+        for route in &config.routes {
+            if let Some(path) = &route.access_log {
+                files.routes.insert(route.name.clone(), LogFile::open(path)?);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Make these the files records are written to.
+    pub fn install(self) {
+        *installed().write().unwrap() = self;
+    }
+}
+
+fn installed() -> &'static RwLock<AccessFiles> {
+    static INSTALLED: OnceLock<RwLock<AccessFiles>> = OnceLock::new();
+    INSTALLED.get_or_init(|| RwLock::new(AccessFiles::default()))
+}
+
+fn file_for(route: &str) -> Option<Arc<LogFile>> {
+    let files = installed().read().unwrap();
+    files.routes.get(route).or(files.default.as_ref()).cloned()
+}
+
+static STRUCTURED_STDOUT: AtomicBool = AtomicBool::new(false);
+
+/// Note whether stdout carries JSON or logfmt records, which [`notice`] keeps clear of.
+pub fn set_stdout_format(format: LogFormat, log_requests: bool) {
+    STRUCTURED_STDOUT.store(log_requests && format.is_structured(), Ordering::Relaxed);
+}
+
+/// Print a status message such as a health change, reload or admin action: on
+/// stdout with text logs, on stderr once stdout carries structured records.
+pub fn notice(message: &str) {
+    if STRUCTURED_STDOUT.load(Ordering::Relaxed) {
+        stderr!("{}", message);
+    } else {
+        echo!("{}", message);
+    }
+}
+
+/// Whether records for a route go anywhere: stdout in a structured format, or
+/// an access log file.
+pub fn wants_records(format: LogFormat, route: &str) -> bool {
+    format.is_structured() || file_for(route).is_some()
+}
@@ -0,0 +1,94 @@
+// Client access lists: allow and deny CIDR ranges
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use crate::balance::client_ip;
+use crate::cfg::Route;
+
+/// An IPv4 or IPv6 network such as `10.0.0.0/8`. A bare address matches only itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(spec: &str) -> Result<Cidr> {
+        let spec = spec.trim();
+        let (address, prefix) = match spec.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (spec, None),
+        };
+        let network: IpAddr = address.parse().context(format!("Invalid address in CIDR '{}'", spec))?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= bits)
+                .ok_or_else(|| anyhow::anyhow!("Invalid prefix length in CIDR '{}'", spec))?,
+            None => bits,
+        };
+        Ok(Cidr { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Dual-stack listeners report IPv4 clients as ::ffff:a.b.c.d
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: String) -> Result<Cidr> {
+        Cidr::parse(&spec)
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> String {
+        cidr.to_string()
+    }
+}
+
+/// A client passes when it matches no `deny` entry and, if `allow` is not
+/// empty, at least one `allow` entry.
+pub fn permits(allow: &[Cidr], deny: &[Cidr], ip: IpAddr) -> bool {
+    !deny.iter().any(|cidr| cidr.contains(ip)) && (allow.is_empty() || allow.iter().any(|cidr| cidr.contains(ip)))
+}
+
+/// Whether a route accepts a client. Unix socket peers have no address and
+/// are always accepted.
+pub fn admits(route: &Route, client_addr: &str) -> bool {
+    match client_ip(client_addr) {
+        Some(ip) => permits(&route.allow, &route.deny, ip),
+        None => true,
+    }
+}
+
+/// Whether a route takes a PROXY header from `peer_addr`: only peers in
+/// `proxy_protocol_from` may vouch for another client's address. Unix socket
+/// peers are local and always trusted.
+pub fn trusts_proxy_header(route: &Route, peer_addr: &str) -> bool {
+    match client_ip(peer_addr) {
+        Some(ip) => route.proxy_protocol_from.iter().any(|cidr| cidr.contains(ip)),
+        None => true,
+    }
+}
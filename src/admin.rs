@@ -0,0 +1,391 @@
+// Local admin API: inspect and change routes and connections at runtime
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use crate::access;
+use crate::balance::BackendPool;
+use crate::cfg::Config;
+use crate::metrics::{self, STATUS_CLASSES};
+use crate::net::{BoxedStream, Endpoint, Listener};
+use crate::reload::{RouteOverride, Supervisor};
+use crate::vhost::normalize_host;
+use rsb::prelude::*;
+
+// Largest request head and body the admin API reads
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_BODY: usize = 64 * 1024;
+
+// Time a client gets to send its whole request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Who may use the admin API and what it may change.
+#[derive(Debug, Clone, Default)]
+pub struct AdminSettings {
+    pub token: Option<String>, // Required as `Authorization: Bearer <token>` when set
+    pub unix_targets: bool,    // Let POST /routes/<name>/target point at unix: sockets
+}
+
+impl AdminSettings {
+    pub fn from_config(config: &Config) -> AdminSettings {
+        AdminSettings { token: config.admin_token.clone(), unix_targets: config.admin_unix_targets }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteStatus {
+    pub name: String,
+    pub mode: String,
+    pub listen: String,
+    pub target: String,
+    pub enabled: bool,
+    pub overridden: bool, // Changed through the admin API
+    pub connections_total: u64,
+    pub active_connections: i64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub rejected_connections: u64, // Refused by allow/deny lists
+    pub http_requests: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConnectionStatus {
+    pub id: u64,
+    pub route: String,
+    pub client: String,
+    pub target: Option<String>,
+    pub duration_seconds: f64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TargetChange {
+    target: String,
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn ok<T: Serialize>(body: T) -> Response {
+        Response { status: 200, body: serde_json::to_string_pretty(&body).unwrap_or_default() }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Response {
+        let body = serde_json::json!({ "error": message.to_string() });
+        Response { status, body: serde_json::to_string_pretty(&body).unwrap_or_default() }
+    }
+}
+
+/// Serve the JSON admin API on `admin_listen`. Only loopback addresses and Unix
+/// sockets are accepted since the API can stop routes and drop connections.
+pub async fn serve_admin(spec: &str, settings: AdminSettings, supervisor: Arc<Mutex<Supervisor>>) -> Result<()> {
+    let endpoint = Endpoint::parse(spec)?;
+    if let Endpoint::Tcp { host, .. } = &endpoint {
+        let loopback = host == "localhost" || host.parse::<IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false);
+        if !loopback {
+            return Err(anyhow::anyhow!("admin_listen must be a loopback address or unix socket, got {}", endpoint));
+        }
+    }
+    let listener = Listener::bind(&endpoint).await?;
+    log::info!("[admin] Serving admin API on {}", endpoint);
+    let settings = Arc::new(settings);
+    let endpoint = Arc::new(endpoint);
+
+    loop {
+        let (client, _, _) = listener.accept().await?;
+        let supervisor = supervisor.clone();
+        let settings = settings.clone();
+        let endpoint = endpoint.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_admin_connection(client, &endpoint, &settings, supervisor).await {
+                log::debug!("[admin] Request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_admin_connection(
+    mut client: BoxedStream,
+    endpoint: &Endpoint,
+    settings: &AdminSettings,
+    supervisor: Arc<Mutex<Supervisor>>,
+) -> Result<()> {
+    let response = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut client)).await {
+        Ok(Ok(request)) => match check_request(&request, endpoint, settings) {
+            Ok(()) => route_request(&request, settings, &supervisor).await,
+            Err(response) => response,
+        },
+        Ok(Err(e)) => Response::error(400, format!("{:#}", e)),
+        Err(_) => Response::error(408, "Timed out reading request"),
+    };
+
+    let body = response.body;
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        _ => "Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        body.len() + 1
+    );
+    client.write_all(head.as_bytes()).await?;
+    client.write_all(body.as_bytes()).await?;
+    client.write_all(b"\n").await?;
+    client.shutdown().await?;
+    Ok(())
+}
+
+/// Turn away requests a web page could forge: a browser on this machine can reach
+/// loopback, so every request needs an expected Host, changes need a JSON content
+/// type (which forces a CORS preflight), and a configured token must match.
+fn check_request(request: &Request, endpoint: &Endpoint, settings: &AdminSettings) -> Result<(), Response> {
+    // Unix sockets are out of a browser's reach, and clients send arbitrary names there
+    if let Endpoint::Tcp { host, .. } = endpoint {
+        let expected = match request.header("host").map(normalize_host) {
+            Some(name) => name == "localhost" || name == "127.0.0.1" || name == "::1" || name == host.to_ascii_lowercase(),
+            None => false,
+        };
+        if !expected {
+            return Err(Response::error(403, "Host must be localhost or the admin_listen address"));
+        }
+    }
+
+    if let Some(token) = &settings.token {
+        let presented = request.header("authorization").and_then(|value| value.strip_prefix("Bearer "));
+        if !presented.is_some_and(|presented| constant_time_eq(presented.trim().as_bytes(), token.as_bytes())) {
+            return Err(Response::error(401, "Missing or wrong bearer token"));
+        }
+    }
+
+    if request.method != "GET" {
+        let json = request
+            .header("content-type")
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"));
+        if !json {
+            return Err(Response::error(415, "Requests that change state need Content-Type: application/json"));
+        }
+    }
+    Ok(())
+}
+
+// Compare without stopping at the first difference, so response timing does not leak the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn read_request(client: &mut BoxedStream) -> Result<Request> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let head_end = loop {
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if data.len() > MAX_HEAD {
+            return Err(anyhow::anyhow!("Request head too large"));
+        }
+        let n = client.read(&mut buffer).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Connection closed before end of request head"));
+        }
+        data.extend_from_slice(&buffer[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.parse::<usize>().context("Invalid Content-Length"))
+        .transpose()?
+        .unwrap_or(0);
+    if content_length > MAX_BODY {
+        return Err(anyhow::anyhow!("Request body too large"));
+    }
+
+    let mut body = data.split_off(head_end + 4);
+    while body.len() < content_length {
+        let n = client.read(&mut buffer).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Connection closed before end of request body"));
+        }
+        body.extend_from_slice(&buffer[..n]);
+    }
+    body.truncate(content_length);
+
+    Ok(Request { method, path, headers, body })
+}
+
+async fn route_request(request: &Request, settings: &AdminSettings, supervisor: &Mutex<Supervisor>) -> Response {
+    let method = request.method.as_str();
+    let body = request.body.as_slice();
+    let path = request.path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["routes"]) => Response::ok(route_statuses(&*supervisor.lock().await)),
+        ("GET", ["routes", name]) => match route_status(&*supervisor.lock().await, name) {
+            Some(status) => Response::ok(status),
+            None => Response::error(404, format!("Unknown route '{}'", name)),
+        },
+        ("POST", ["routes", name, "enable"]) => {
+            update_route(supervisor, name, RouteOverride { enabled: Some(true), target: None }).await
+        }
+        ("POST", ["routes", name, "disable"]) => {
+            update_route(supervisor, name, RouteOverride { enabled: Some(false), target: None }).await
+        }
+        ("POST", ["routes", name, "target"]) => {
+            let change: TargetChange = match serde_json::from_slice(body) {
+                Ok(change) => change,
+                Err(e) => return Response::error(400, format!("Expected {{\"target\": \"host:port\"}}: {}", e)),
+            };
+            match Endpoint::parse(&change.target) {
+                Err(e) => return Response::error(400, e),
+                Ok(Endpoint::Unix(_)) if !settings.unix_targets => {
+                    return Response::error(403, "unix: targets need admin_unix_targets = true");
+                }
+                Ok(_) => {}
+            }
+            update_route(supervisor, name, RouteOverride { enabled: None, target: Some(change.target) }).await
+        }
+        ("GET", ["connections"]) => {
+            let connections = supervisor.lock().await.connections().clone();
+            Response::ok(
+                connections
+                    .list()
+                    .iter()
+                    .map(|info| ConnectionStatus {
+                        id: info.id,
+                        route: info.listener.clone(),
+                        client: info.client.clone(),
+                        target: info.target(),
+                        duration_seconds: info.started.elapsed().as_secs_f64(),
+                        bytes_in: info.bytes_in.load(Ordering::Relaxed),
+                        bytes_out: info.bytes_out.load(Ordering::Relaxed),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }
+        ("DELETE", ["connections", id]) => {
+            let id: u64 = match id.parse() {
+                Ok(id) => id,
+                Err(_) => return Response::error(400, format!("Invalid connection id '{}'", id)),
+            };
+            let connections = supervisor.lock().await.connections().clone();
+            if connections.kill(id) {
+                access::notice(&format!("🔪 Connection {} closed via admin API", id));
+                Response::ok(serde_json::json!({ "killed": id }))
+            } else {
+                Response::error(404, format!("No open connection {}", id))
+            }
+        }
+        (_, ["routes"]) | (_, ["routes", _]) | (_, ["routes", _, "enable" | "disable" | "target"])
+        | (_, ["connections"]) | (_, ["connections", _]) => Response::error(405, "Method not allowed"),
+        _ => Response::error(404, format!("No such endpoint: {}", path)),
+    }
+}
+
+async fn update_route(supervisor: &Mutex<Supervisor>, name: &str, change: RouteOverride) -> Response {
+    let mut supervisor = supervisor.lock().await;
+    if route_status(&supervisor, name).is_none() {
+        return Response::error(404, format!("Unknown route '{}'", name));
+    }
+
+    let description = match (&change.enabled, &change.target) {
+        (Some(true), _) => "enabled".to_string(),
+        (Some(false), _) => "disabled".to_string(),
+        (None, Some(target)) => format!("target set to {}", target),
+        (None, None) => "unchanged".to_string(),
+    };
+    match supervisor.update_route(name, change).await {
+        Ok(()) => {
+            access::notice(&format!("🛠️  Route '{}' {} via admin API", name, description));
+            Response::ok(route_status(&supervisor, name))
+        }
+        Err(e) => Response::error(409, format!("{:#}", e)),
+    }
+}
+
+fn route_statuses(supervisor: &Supervisor) -> Vec<RouteStatus> {
+    supervisor.routes().iter().map(|route| status_for(supervisor, route)).collect()
+}
+
+fn route_status(supervisor: &Supervisor, name: &str) -> Option<RouteStatus> {
+    supervisor.routes().iter().find(|r| r.name == name).map(|route| status_for(supervisor, route))
+}
+
+fn status_for(supervisor: &Supervisor, route: &crate::cfg::Route) -> RouteStatus {
+    let listen_addr = supervisor.config().map(|c| c.listen_addr.as_str()).unwrap_or_default();
+    let listen = route.listen_endpoint(listen_addr).map(|e| e.to_string()).unwrap_or_default();
+    let target = BackendPool::from_route(route).map(|pool| pool.label()).unwrap_or_default();
+
+    // Stream counters live under the listener name, which is the route name unless
+    // several HTTP routes share the address
+    let stats = metrics::get(&route.name);
+    let load = |value: fn(&metrics::RouteMetrics) -> u64| stats.as_deref().map(value).unwrap_or(0);
+    let http_requests = STATUS_CLASSES
+        .iter()
+        .enumerate()
+        .map(|(i, class)| {
+            let count = stats.as_deref().map(|m| m.http_responses[i].load(Ordering::Relaxed)).unwrap_or(0);
+            (class.to_string(), count)
+        })
+        .collect();
+
+    RouteStatus {
+        name: route.name.clone(),
+        mode: route.mode.clone(),
+        listen,
+        target,
+        enabled: route.enabled,
+        overridden: supervisor.is_overridden(&route.name),
+        connections_total: load(|m| m.connections.load(Ordering::Relaxed)),
+        active_connections: stats.as_deref().map(|m| m.active_connections.load(Ordering::Relaxed)).unwrap_or(0),
+        bytes_received: load(|m| m.bytes_received.load(Ordering::Relaxed)),
+        bytes_sent: load(|m| m.bytes_sent.load(Ordering::Relaxed)),
+        rejected_connections: load(|m| m.rejected_connections.load(Ordering::Relaxed)),
+        http_requests,
+    }
+}
@@ -0,0 +1,304 @@
+// Backend pools and load-balancing strategies
+
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use crate::cfg::Route;
+use crate::net::Endpoint;
+
+// Ring points per unit of weight for consistent hashing
+const HASH_POINTS_PER_WEIGHT: u32 = 100;
+
+// Highest weight a backend may have
+const MAX_WEIGHT: u32 = 1000;
+
+/// One entry of a route's `targets` pool.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BackendTarget {
+    pub target: String, // "host:port" or "unix:/path"
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 { 1 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    RoundRobin,
+    LeastConnections,
+    Random,
+    ConsistentHash,
+}
+
+impl Strategy {
+    pub fn parse(name: &str) -> Result<Strategy> {
+        match name {
+            "" | "round-robin" => Ok(Strategy::RoundRobin),
+            "least-connections" => Ok(Strategy::LeastConnections),
+            "random" => Ok(Strategy::Random),
+            "consistent-hash" => Ok(Strategy::ConsistentHash),
+            other => Err(anyhow::anyhow!(
+                "Unknown balance strategy '{}' (expected round-robin, least-connections, random or consistent-hash)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Strategy::RoundRobin => "round-robin",
+            Strategy::LeastConnections => "least-connections",
+            Strategy::Random => "random",
+            Strategy::ConsistentHash => "consistent-hash",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Backend {
+    pub endpoint: Endpoint,
+    pub weight: u32,
+    active: AtomicUsize,
+    healthy: AtomicBool,
+}
+
+impl Backend {
+    fn new(endpoint: Endpoint, weight: u32) -> Backend {
+        Backend { endpoint, weight, active: AtomicUsize::new(0), healthy: AtomicBool::new(true) }
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Backends start healthy; health checks take them out of rotation.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed);
+    }
+}
+
+/// What a request offers as input to the balancing decision.
+#[derive(Debug, Default)]
+pub struct BalanceContext<'a> {
+    pub client_ip: Option<IpAddr>,
+    pub header_value: Option<&'a str>,
+}
+
+/// IP part of a printable peer address; `None` for unix socket peers.
+pub fn client_ip(client_addr: &str) -> Option<IpAddr> {
+    client_addr.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// A backend picked for one connection. Counts as active until dropped.
+#[derive(Debug)]
+pub struct BackendLease {
+    backend: Arc<Backend>,
+}
+
+impl BackendLease {
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.backend.endpoint
+    }
+}
+
+impl Drop for BackendLease {
+    fn drop(&mut self) {
+        self.backend.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct BackendPool {
+    backends: Vec<Arc<Backend>>,
+    strategy: Strategy,
+    hash_header: Option<String>,
+    ring: Vec<(u64, usize)>,
+    next: AtomicUsize,
+}
+
+impl BackendPool {
+    /// Build the pool from `targets`, or from the single `target`/`target_addr` when unset.
+    pub fn from_route(route: &Route) -> Result<BackendPool> {
+        let backends: Vec<Arc<Backend>> = if route.targets.is_empty() {
+            vec![Arc::new(Backend::new(route.target_endpoint()?, 1))]
+        } else {
+            route.targets.iter()
+                .map(|t| {
+                    let endpoint = Endpoint::parse(&t.target)
+                        .context(format!("Invalid backend '{}' on route '{}'", t.target, route.name))?;
+                    Ok(Arc::new(Backend::new(endpoint, t.weight)))
+                })
+                .collect::<Result<_>>()?
+        };
+
+        // Keeps the consistent-hash ring small and its point count from overflowing
+        if let Some(heavy) = backends.iter().find(|b| b.weight > MAX_WEIGHT) {
+            return Err(anyhow::anyhow!(
+                "Backend {} on route '{}' has weight {}, above the limit of {}",
+                heavy.endpoint, route.name, heavy.weight, MAX_WEIGHT
+            ));
+        }
+        if backends.iter().all(|b| b.weight == 0) {
+            return Err(anyhow::anyhow!("Route '{}' has no backend with a non-zero weight", route.name));
+        }
+
+        if let Some(check) = &route.health_check {
+            check.validate().context(format!("Invalid health_check on route '{}'", route.name))?;
+        }
+
+        let strategy = Strategy::parse(&route.balance)
+            .context(format!("Invalid balance setting on route '{}'", route.name))?;
+
+        let hash_header = match route.hash_key.as_str() {
+            "client-ip" => None,
+            key => match key.strip_prefix("header:") {
+                Some(name) if !name.is_empty() => Some(name.to_ascii_lowercase()),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Invalid hash_key '{}' on route '{}' (expected client-ip or header:<name>)",
+                        key, route.name
+                    ))
+                }
+            },
+        };
+
+        let ring = if strategy == Strategy::ConsistentHash {
+            build_ring(&backends)
+        } else {
+            Vec::new()
+        };
+
+        Ok(BackendPool { backends, strategy, hash_header, ring, next: AtomicUsize::new(0) })
+    }
+
+    pub fn backends(&self) -> &[Arc<Backend>] {
+        &self.backends
+    }
+
+    /// Take over the health of backends `previous` also had, so a reload does not
+    /// put known-down backends back into rotation before their next check.
+    pub fn inherit_health(&self, previous: &BackendPool) {
+        for backend in &self.backends {
+            if let Some(old) = previous.backends.iter().find(|old| old.endpoint == backend.endpoint) {
+                backend.set_healthy(old.is_healthy());
+            }
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Printable target for logs: the lone backend, or a pool summary.
+    pub fn label(&self) -> String {
+        match self.backends.as_slice() {
+            [only] => only.endpoint.to_string(),
+            backends => format!("{} backends ({})", backends.len(), self.strategy),
+        }
+    }
+
+    /// Header whose value feeds consistent hashing, lowercased (HTTP routes only).
+    pub fn hash_header(&self) -> Option<&str> {
+        self.hash_header.as_deref()
+    }
+
+    /// Pick a healthy backend for a new connection or request.
+    pub fn select(&self, ctx: &BalanceContext<'_>) -> Option<BackendLease> {
+        let candidates: Vec<usize> = (0..self.backends.len())
+            .filter(|&i| self.backends[i].weight > 0 && self.backends[i].is_healthy())
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let index = match self.strategy {
+            Strategy::RoundRobin => {
+                let total: u64 = candidates.iter().map(|&i| self.backends[i].weight as u64).sum();
+                let slot = self.next.fetch_add(1, Ordering::Relaxed) as u64 % total;
+                pick_weighted(&self.backends, &candidates, slot)
+            }
+            Strategy::Random => {
+                let total: u64 = candidates.iter().map(|&i| self.backends[i].weight as u64).sum();
+                let slot = rand::thread_rng().gen_range(0..total);
+                pick_weighted(&self.backends, &candidates, slot)
+            }
+            Strategy::LeastConnections => {
+                // Fewest active connections relative to weight: a/wa < b/wb  <=>  a*wb < b*wa
+                *candidates.iter().min_by(|&&a, &&b| {
+                    let (ba, bb) = (&self.backends[a], &self.backends[b]);
+                    (ba.active_connections() as u64 * bb.weight as u64)
+                        .cmp(&(bb.active_connections() as u64 * ba.weight as u64))
+                })?
+            }
+            Strategy::ConsistentHash => {
+                let key = match (ctx.header_value, ctx.client_ip) {
+                    (Some(value), _) => value.to_string(),
+                    (None, Some(ip)) => ip.to_string(),
+                    (None, None) => String::new(),
+                };
+                self.ring_lookup(fnv1a(key.as_bytes()))?
+            }
+        };
+
+        let backend = self.backends[index].clone();
+        backend.active.fetch_add(1, Ordering::Relaxed);
+        Some(BackendLease { backend })
+    }
+
+    // First point at or after the hash owned by a healthy backend, so only the
+    // keys of a down backend move elsewhere
+    fn ring_lookup(&self, hash: u64) -> Option<usize> {
+        let pos = self.ring.partition_point(|&(point, _)| point < hash);
+        (0..self.ring.len())
+            .map(|offset| self.ring[(pos + offset) % self.ring.len()].1)
+            .find(|&index| self.backends[index].is_healthy())
+    }
+}
+
+fn pick_weighted(backends: &[Arc<Backend>], candidates: &[usize], mut slot: u64) -> usize {
+    for &i in candidates {
+        let weight = backends[i].weight as u64;
+        if slot < weight {
+            return i;
+        }
+        slot -= weight;
+    }
+    candidates[candidates.len() - 1]
+}
+
+fn build_ring(backends: &[Arc<Backend>]) -> Vec<(u64, usize)> {
+    let mut ring = Vec::new();
+    for (i, backend) in backends.iter().enumerate() {
+        for point in 0..backend.weight * HASH_POINTS_PER_WEIGHT {
+            let key = format!("{}#{}", backend.endpoint, point);
+            ring.push((fnv1a(key.as_bytes()), i));
+        }
+    }
+    ring.sort_unstable();
+    ring
+}
+
+// Stable across runs and hosts, unlike std's randomized hasher. The final mix
+// spreads keys that differ only in their last bytes across the whole ring.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash
+}
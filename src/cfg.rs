@@ -5,28 +5,159 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use rsb::prelude::*;
+use crate::acl::Cidr;
+use crate::balance::BackendTarget;
+use crate::dynamic::DynamicRouting;
+use crate::forwarded::ForwardedHeaders;
+use crate::health::HealthCheck;
+use crate::http::{HostHeader, PathRule};
+use crate::logfile::LogRotation;
+use crate::net::Endpoint;
+use crate::proxy_protocol::ProxyVersion;
+use crate::tls::{SniTarget, TlsCertificate};
+use crate::vhost::HostList;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Route {
     pub name: String,
+    #[serde(default)]
     pub listen_port: u16,
+    #[serde(default)]
     pub target_addr: String,
+    #[serde(default)]
     pub target_port: u16,
     #[serde(default)]
+    pub listen: Option<String>, // "host:port" or "unix:/path", overrides listen_port
+    #[serde(default)]
+    pub target: Option<String>, // "host:port" or "unix:/path", overrides target_addr/target_port
+    #[serde(default)]
     pub enabled: bool,
     #[serde(default)]
-    pub mode: String, // "tcp" or "http"
+    pub mode: String, // "tcp", "http", "udp" or "tls-passthrough"
     #[serde(default)]
-    pub host: Option<String>, // Host header matching
+    pub host: Option<HostList>, // Host header matching: name, "*.domain" or "~regex", or a list
+    #[serde(default)]
+    pub default: bool, // Serve unmatched hosts when HTTP routes share a listen address
     #[serde(default = "default_log_level")]
     pub log_level: String, // "none", "basic", "verbose"
     #[serde(default = "default_timeout_seconds")]
     pub timeout_seconds: u64, // Connection timeout in seconds
     #[serde(default = "default_max_retries")]
     pub max_retries: u32, // Max retry attempts
+    #[serde(default = "default_session_timeout_seconds")]
+    pub session_timeout_seconds: u64, // UDP session idle expiry in seconds
+    #[serde(default = "default_keepalive_timeout_seconds")]
+    pub keepalive_timeout_seconds: u64, // HTTP idle time between requests; 0 closes after each response
+    #[serde(default = "default_upgrade_timeout_seconds")]
+    pub upgrade_timeout_seconds: u64, // Idle expiry for upgraded (WebSocket) HTTP connections; 0 never expires
+    #[serde(default)]
+    pub max_request_body: Option<u64>, // HTTP request body limit in bytes; larger bodies get a 413
+    #[serde(default)]
+    pub tls_cert: Option<String>, // PEM certificate chain; enables TLS termination
+    #[serde(default)]
+    pub tls_key: Option<String>, // PEM private key for tls_cert
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tls_certs: Vec<TlsCertificate>, // Extra certificates selected by SNI
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sni_targets: Vec<SniTarget>, // SNI -> backend table (tls-passthrough mode)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<PathRule>, // Path-prefix backends (HTTP mode)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<BackendTarget>, // Backend pool, replaces target/target_addr
+    #[serde(default = "default_balance")]
+    pub balance: String, // "round-robin", "least-connections", "random", "consistent-hash"
+    #[serde(default = "default_hash_key")]
+    pub hash_key: String, // "client-ip" or "header:<name>" for consistent-hash
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>, // Active checks; failing backends leave rotation
+    #[serde(default, skip_serializing_if = "DynamicRouting::is_default")]
+    pub dynamic: DynamicRouting, // Which porty_host/porty_port targets HTTP mode may reach
+    #[serde(default, skip_serializing_if = "ForwardedHeaders::is_default")]
+    pub forwarded: ForwardedHeaders, // X-Forwarded-*, X-Real-IP and Forwarded headers (HTTP mode)
+    #[serde(default)]
+    pub host_header: HostHeader, // "preserve", "target" or "custom:<value>" (HTTP mode)
+    #[serde(default)]
+    pub access_log: Option<String>, // Record file for this route, overrides the top-level access_log
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<Cidr>, // Client ranges let in; replaces the top-level allow list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<Cidr>, // Client ranges refused, on top of the top-level deny list
+    #[serde(default)]
+    pub send_proxy_protocol: Option<ProxyVersion>, // "v1" or "v2" header to the backend (tcp, tls-passthrough)
+    #[serde(default)]
+    pub accept_proxy_protocol: bool, // Clients start with a PROXY header carrying the real address
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_protocol_from: Vec<Cidr>, // Load balancers allowed to send that header
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Default for Route {
+    fn default() -> Self {
+        Route {
+            name: String::new(),
+            listen_port: 0,
+            target_addr: String::new(),
+            target_port: 0,
+            listen: None,
+            target: None,
+            enabled: false,
+            mode: "tcp".to_string(),
+            host: None,
+            default: false,
+            log_level: default_log_level(),
+            timeout_seconds: default_timeout_seconds(),
+            max_retries: default_max_retries(),
+            session_timeout_seconds: default_session_timeout_seconds(),
+            keepalive_timeout_seconds: default_keepalive_timeout_seconds(),
+            upgrade_timeout_seconds: default_upgrade_timeout_seconds(),
+            max_request_body: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_certs: vec![],
+            sni_targets: vec![],
+            paths: vec![],
+            targets: vec![],
+            balance: default_balance(),
+            hash_key: default_hash_key(),
+            health_check: None,
+            dynamic: DynamicRouting::default(),
+            forwarded: ForwardedHeaders::default(),
+            host_header: HostHeader::default(),
+            access_log: None,
+            allow: vec![],
+            deny: vec![],
+            send_proxy_protocol: None,
+            accept_proxy_protocol: false,
+            proxy_protocol_from: vec![],
+        }
+    }
+}
+
+impl Route {
+    pub fn listen_endpoint(&self, listen_addr: &str) -> Result<Endpoint> {
+        match &self.listen {
+            Some(spec) => Endpoint::parse(spec)
+                .context(format!("Invalid listen address for route '{}'", self.name)),
+            None if self.listen_port == 0 => {
+                Err(anyhow::anyhow!("Route '{}' has no listen address (set listen or listen_port)", self.name))
+            }
+            None => Ok(Endpoint::tcp(listen_addr, self.listen_port)),
+        }
+    }
+
+    pub fn target_endpoint(&self) -> Result<Endpoint> {
+        match &self.target {
+            Some(spec) => Endpoint::parse(spec)
+                .context(format!("Invalid target address for route '{}'", self.name)),
+            None if self.target_addr.is_empty() || self.target_port == 0 => Err(anyhow::anyhow!(
+                "Route '{}' has no target (set target, targets, or target_addr and target_port)",
+                self.name
+            )),
+            None => Ok(Endpoint::tcp(&self.target_addr, self.target_port)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default = "default_listen_addr")]
     pub listen_addr: String,
@@ -52,6 +183,36 @@ pub struct Config {
     #[serde(default = "default_log_format")]
     pub log_format: String,
 
+    #[serde(default = "default_drain_timeout_seconds")]
+    pub drain_timeout_seconds: u64,
+
+    #[serde(default)]
+    pub access_log: Option<String>, // File for access records instead of stdout
+
+    #[serde(default)]
+    pub error_log: Option<String>, // File for errors and log messages instead of stderr
+
+    #[serde(default)]
+    pub metrics_listen: Option<String>, // "host:port" or "unix:/path" for Prometheus scrapes
+
+    #[serde(default)]
+    pub admin_listen: Option<String>, // Loopback "host:port" or "unix:/path" for the admin API
+
+    #[serde(default)]
+    pub admin_token: Option<String>, // Bearer token the admin API requires when set
+
+    #[serde(default)]
+    pub admin_unix_targets: bool, // Let the admin API point routes at unix: sockets
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<Cidr>, // Client ranges let in on every route; empty allows all
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<Cidr>, // Client ranges refused on every route
+
+    #[serde(default)]
+    pub log_rotation: LogRotation, // Applies to access_log, error_log and per-route access logs
+
     #[serde(default)]
     pub routes: Vec<Route>,
 }
@@ -65,9 +226,15 @@ fn default_max_connections() -> usize { 100 }
 fn default_buffer_size() -> usize { 8 }
 fn default_log_requests() -> bool { true }
 fn default_log_format() -> String { "default".to_string() }
+fn default_drain_timeout_seconds() -> u64 { 30 }
 fn default_log_level() -> String { "basic".to_string() }
 fn default_timeout_seconds() -> u64 { 30 }
 fn default_max_retries() -> u32 { 2 }
+fn default_session_timeout_seconds() -> u64 { 60 }
+fn default_keepalive_timeout_seconds() -> u64 { 60 }
+fn default_upgrade_timeout_seconds() -> u64 { 600 }
+fn default_balance() -> String { "round-robin".to_string() }
+fn default_hash_key() -> String { "client-ip".to_string() }
 
 impl Default for Config {
     fn default() -> Self {
@@ -80,11 +247,61 @@ impl Default for Config {
             buffer_size_kb: default_buffer_size(),
             log_requests: default_log_requests(),
             log_format: default_log_format(),
+            drain_timeout_seconds: default_drain_timeout_seconds(),
+            access_log: None,
+            error_log: None,
+            metrics_listen: None,
+            admin_listen: None,
+            admin_token: None,
+            admin_unix_targets: false,
+            allow: vec![],
+            deny: vec![],
+            log_rotation: LogRotation::default(),
             routes: vec![],
         }
     }
 }
 
+impl Config {
+    /// Apply --listen-port style overrides from the global context (RSB pattern).
+    pub fn apply_overrides(&mut self) {
+        if has_var("opt_listen_port") {
+            self.listen_port = get_var("opt_listen_port").parse().unwrap_or(self.listen_port);
+        }
+        if has_var("opt_target_port") {
+            self.target_port = get_var("opt_target_port").parse().unwrap_or(self.target_port);
+        }
+        if has_var("opt_listen_addr") {
+            self.listen_addr = get_var("opt_listen_addr");
+        }
+        if has_var("opt_target_addr") {
+            self.target_addr = get_var("opt_target_addr");
+        }
+    }
+
+    /// The top-level listen/target pair expressed as a plain TCP route.
+    pub fn main_route(&self) -> Route {
+        Route {
+            name: "main".to_string(),
+            listen_port: self.listen_port,
+            target_addr: self.target_addr.clone(),
+            target_port: self.target_port,
+            enabled: true,
+            ..Route::default()
+        }
+    }
+
+    /// Layer the top-level access lists over a route's own: a route `allow` list
+    /// replaces the global one, while deny entries from both apply.
+    pub fn with_global_lists(&self, mut route: Route) -> Route {
+        if route.allow.is_empty() {
+            route.allow = self.allow.clone();
+        }
+        route.deny = self.deny.iter().chain(&route.deny).copied().collect();
+        route
+    }
+}
+
 pub fn load_config(path: &PathBuf) -> Result<Config> {
     if !path.exists() {
         echo!("⚠️  Config file not found at: {}", path.display());
@@ -112,42 +329,44 @@ pub fn generate_example_config(path: &PathBuf) -> Result<()> {
         buffer_size_kb: 8,
         log_requests: true,
         log_format: "default".to_string(),
+        drain_timeout_seconds: 30,
+        access_log: None,
+        error_log: None,
+        metrics_listen: None,
+        admin_listen: None,
+        admin_token: None,
+        admin_unix_targets: false,
+        allow: vec![],
+        deny: vec![],
+        log_rotation: LogRotation::default(),
         routes: vec![
             Route {
                 name: "web".to_string(),
                 listen_port: 8080,
                 target_addr: "127.0.0.1".to_string(),
                 target_port: 80,
-                enabled: false,
                 mode: "tcp".to_string(),
-                host: None,
-                log_level: default_log_level(),
-                timeout_seconds: default_timeout_seconds(),
-                max_retries: default_max_retries(),
+                ..Route::default()
             },
             Route {
                 name: "api-host-routing".to_string(),
                 listen_port: 9080,
                 target_addr: "api.example.com".to_string(),
                 target_port: 80,
-                enabled: false,
                 mode: "http".to_string(),
-                host: Some("api.example.com".to_string()),
+                host: Some(HostList::One("api.example.com".to_string())),
                 log_level: "verbose".to_string(),
                 timeout_seconds: 10,
                 max_retries: 3,
+                ..Route::default()
             },
             Route {
                 name: "ssh".to_string(),
                 listen_port: 2222,
                 target_addr: "127.0.0.1".to_string(),
                 target_port: 22,
-                enabled: false,
                 mode: "tcp".to_string(),
-                host: None,
-                log_level: default_log_level(),
-                timeout_seconds: default_timeout_seconds(),
-                max_retries: default_max_retries(),
+                ..Route::default()
             },
         ],
     };
@@ -0,0 +1,170 @@
+// Dynamic routing policy: which porty_host/porty_port targets a route may reach
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use crate::access::{classify, ErrorKind};
+use crate::acl::Cidr;
+use crate::net::Endpoint;
+
+/// The `[routes.dynamic]` table. Targets resolving to loopback or link-local
+/// addresses are refused unless `allow_cidrs` covers them.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DynamicRouting {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool, // false ignores porty_host/porty_port
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_hosts: Vec<String>, // Exact names or "*.domain" wildcards (one label)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_cidrs: Vec<Cidr>, // Resolved addresses let through
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_ports: Vec<PortRange>, // 443 or "8000-8999"; empty allows any port
+}
+
+fn default_enabled() -> bool { true }
+
+impl Default for DynamicRouting {
+    fn default() -> Self {
+        DynamicRouting {
+            enabled: default_enabled(),
+            allow_hosts: vec![],
+            allow_cidrs: vec![],
+            allow_ports: vec![],
+        }
+    }
+}
+
+/// An inclusive port range, written as `443` or `"8000-8999"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "PortSpec", into = "PortSpec")]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum PortSpec {
+    Port(u16),
+    Range(String),
+}
+
+impl PortRange {
+    pub fn parse(spec: &str) -> Result<PortRange> {
+        let invalid = || anyhow::anyhow!("Invalid port range '{}' (expected 443 or \"8000-8999\")", spec);
+        let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+        let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(PortRange { start, end })
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl TryFrom<PortSpec> for PortRange {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: PortSpec) -> Result<PortRange> {
+        match spec {
+            PortSpec::Port(port) => Ok(PortRange { start: port, end: port }),
+            PortSpec::Range(range) => PortRange::parse(&range),
+        }
+    }
+}
+
+impl From<PortRange> for PortSpec {
+    fn from(range: PortRange) -> PortSpec {
+        if range.start == range.end {
+            PortSpec::Port(range.start)
+        } else {
+            PortSpec::Range(range.to_string())
+        }
+    }
+}
+
+impl DynamicRouting {
+    pub fn is_default(&self) -> bool {
+        *self == DynamicRouting::default()
+    }
+
+    /// Check a porty_host/porty_port target and resolve it. Refusals are tagged
+    /// `Forbidden`, lookup failures `Connect`. Connect to the returned address
+    /// instead of resolving the name again, so a second DNS answer cannot
+    /// bypass the check.
+    pub async fn authorize(&self, target: &Endpoint) -> Result<SocketAddr> {
+        let (host, port) = match target {
+            Endpoint::Tcp { host, port } => (host.as_str(), *port),
+            Endpoint::Unix(_) => return Err(denied(target, "unix sockets cannot be dynamic targets")),
+        };
+
+        if !self.allow_ports.is_empty() && !self.allow_ports.iter().any(|range| range.contains(port)) {
+            return Err(denied(target, &format!("port {} is not allowed", port)));
+        }
+
+        let restricted = !self.allow_hosts.is_empty() || !self.allow_cidrs.is_empty();
+        let host_allowed = self.allows_host(host);
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| classify(ErrorKind::Connect, anyhow::anyhow!("Failed to resolve {}: {}", host, e)))?
+            .collect();
+
+        // Every address must pass, so one name cannot mix allowed and refused addresses
+        for address in &addresses {
+            let ip = address.ip();
+            let listed = self.allow_cidrs.iter().any(|cidr| cidr.contains(ip));
+            if is_local(ip) && !listed {
+                return Err(denied(target, &format!("resolves to loopback or link-local address {}", ip)));
+            }
+            if restricted && !host_allowed && !listed {
+                return Err(denied(target, "not in allow_hosts or allow_cidrs"));
+            }
+        }
+
+        addresses.first().copied().ok_or_else(|| {
+            classify(ErrorKind::Connect, anyhow::anyhow!("{} did not resolve to any address", host))
+        })
+    }
+
+    /// Whether `allow_hosts` lists the name. A `*.domain` wildcard covers exactly
+    /// one label, as in virtual host and SNI matching.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.allow_hosts.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.strip_prefix('*') {
+                Some(suffix) if suffix.starts_with('.') => host
+                    .strip_suffix(suffix)
+                    .is_some_and(|label| !label.is_empty() && !label.contains('.')),
+                _ => host == pattern,
+            }
+        })
+    }
+}
+
+fn denied(target: &Endpoint, reason: &str) -> anyhow::Error {
+    classify(ErrorKind::Forbidden, anyhow::anyhow!("Dynamic target {} denied: {}", target, reason))
+}
+
+// Loopback, link-local (cloud metadata lives at 169.254.169.254) and unspecified
+fn is_local(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unspecified() || (ip.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
@@ -0,0 +1,101 @@
+// Client identity headers: X-Forwarded-*, X-Real-IP and RFC 7239 Forwarded
+
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use crate::acl::Cidr;
+use crate::balance::client_ip;
+use crate::headers::Headers;
+
+/// The `[routes.forwarded]` table. Each enabled header is added to forwarded
+/// requests. Values a client sent are kept and extended only when the client is
+/// in `trusted_proxies`; from anyone else they are replaced.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ForwardedHeaders {
+    #[serde(default)]
+    pub x_forwarded_for: bool, // Append the client address
+    #[serde(default)]
+    pub x_forwarded_proto: bool, // "http" or "https"
+    #[serde(default)]
+    pub x_forwarded_host: bool, // The Host header the client sent
+    #[serde(default)]
+    pub x_real_ip: bool, // The original client address
+    #[serde(default)]
+    pub forwarded: bool, // RFC 7239 for/proto/host element
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<Cidr>, // Peers whose incoming values are kept
+}
+
+impl ForwardedHeaders {
+    pub fn is_default(&self) -> bool {
+        *self == ForwardedHeaders::default()
+    }
+
+    pub fn trusts(&self, client_addr: &str) -> bool {
+        client_ip(client_addr).is_some_and(|ip| self.trusted_proxies.iter().any(|cidr| cidr.contains(ip)))
+    }
+
+    /// Add the enabled headers for a request from `client_addr` that arrived over
+    /// `proto`. Call before the Host header is rewritten for the backend.
+    pub fn apply(&self, headers: &mut Headers, client_addr: &str, proto: &str) {
+        let ip = client_ip(client_addr).map(|ip| ip.to_canonical());
+        let trusted = self.trusts(client_addr);
+        let host = headers.get("Host").map(str::to_string);
+
+        if self.x_forwarded_for {
+            if let Some(ip) = ip {
+                let chain = headers.get_all("X-Forwarded-For").collect::<Vec<_>>().join(", ");
+                if trusted && !chain.is_empty() {
+                    headers.set("X-Forwarded-For", &format!("{}, {}", chain, ip));
+                } else {
+                    headers.set("X-Forwarded-For", &ip.to_string());
+                }
+            } else if !trusted {
+                headers.remove("X-Forwarded-For");
+            }
+        }
+        if self.x_real_ip {
+            match ip {
+                Some(_) if trusted && headers.contains("X-Real-IP") => {}
+                Some(ip) => headers.set("X-Real-IP", &ip.to_string()),
+                None => headers.remove("X-Real-IP"),
+            }
+        }
+        if self.x_forwarded_proto && !(trusted && headers.contains("X-Forwarded-Proto")) {
+            headers.set("X-Forwarded-Proto", proto);
+        }
+        if self.x_forwarded_host && !(trusted && headers.contains("X-Forwarded-Host")) {
+            match &host {
+                Some(host) => headers.set("X-Forwarded-Host", host),
+                None => headers.remove("X-Forwarded-Host"),
+            }
+        }
+        if self.forwarded {
+            let element = forwarded_element(ip, proto, host.as_deref());
+            let previous = headers.get_all("Forwarded").collect::<Vec<_>>().join(", ");
+            if trusted && !previous.is_empty() {
+                headers.set("Forwarded", &format!("{}, {}", previous, element));
+            } else {
+                headers.set("Forwarded", &element);
+            }
+        }
+    }
+}
+
+/// One `Forwarded` element such as `for=192.0.2.60;proto=https;host=example.com`.
+/// IPv6 addresses and hosts with a port are quoted; Unix socket peers are `unknown`.
+pub fn forwarded_element(ip: Option<IpAddr>, proto: &str, host: Option<&str>) -> String {
+    let node = match ip {
+        Some(IpAddr::V6(ip)) => format!("\"[{}]\"", ip),
+        Some(ip) => ip.to_string(),
+        None => "unknown".to_string(),
+    };
+    let mut element = format!("for={};proto={}", node, proto);
+    if let Some(host) = host {
+        if host.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c)) {
+            element.push_str(&format!(";host={}", host));
+        } else {
+            element.push_str(&format!(";host=\"{}\"", host.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+    }
+    element
+}
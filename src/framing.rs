@@ -0,0 +1,334 @@
+// HTTP/1.1 message framing: header blocks and Content-Length, chunked or close-delimited bodies
+
+use anyhow::Result;
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::access::{classify, ErrorKind};
+use crate::headers::Headers;
+
+const MAX_LINE: u64 = 16 * 1024;
+const MAX_HEAD: usize = 64 * 1024;
+
+/// How the end of a message body is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFraming {
+    Empty,
+    Length(u64),
+    Chunked,
+    UntilClose, // Response bodies without a length end when the backend closes
+}
+
+impl BodyFraming {
+    /// Framing of a request body (RFC 9112 section 6.3). Requests carrying both
+    /// Transfer-Encoding and Content-Length are refused, since a proxy and its
+    /// backend could disagree on where the body ends.
+    pub fn for_request(headers: &Headers) -> Result<BodyFraming> {
+        if headers.contains("Transfer-Encoding") {
+            if headers.contains("Content-Length") {
+                return Err(anyhow::anyhow!("Both Transfer-Encoding and Content-Length present"));
+            }
+            if !is_chunked(headers) {
+                return Err(anyhow::anyhow!("Transfer-Encoding must end with chunked"));
+            }
+            return Ok(BodyFraming::Chunked);
+        }
+        Ok(match content_length(headers)? {
+            Some(0) | None => BodyFraming::Empty,
+            Some(length) => BodyFraming::Length(length),
+        })
+    }
+
+    /// Framing of the response to a `method` request with status `code` (RFC 9112 section 6.3).
+    pub fn for_response(method: &str, code: u16, headers: &Headers) -> Result<BodyFraming> {
+        if method.eq_ignore_ascii_case("HEAD") || (100..200).contains(&code) || code == 204 || code == 304 {
+            return Ok(BodyFraming::Empty);
+        }
+        if headers.contains("Transfer-Encoding") {
+            return Ok(if is_chunked(headers) { BodyFraming::Chunked } else { BodyFraming::UntilClose });
+        }
+        Ok(match content_length(headers)? {
+            Some(length) => BodyFraming::Length(length),
+            None => BodyFraming::UntilClose,
+        })
+    }
+}
+
+/// Whether `chunked` is the final transfer coding.
+pub fn is_chunked(headers: &Headers) -> bool {
+    headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .last()
+        .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+}
+
+/// The Content-Length value. Repeated fields must agree.
+pub fn content_length(headers: &Headers) -> Result<Option<u64>> {
+    let mut length = None;
+    for value in headers.get_all("Content-Length").flat_map(|value| value.split(',')) {
+        // 1*DIGIT only: parse() would also take "+5", which backends may read differently
+        let value = value.trim();
+        let parsed: u64 = Some(value)
+            .filter(|value| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid Content-Length '{}'", value))?;
+        if length.is_some_and(|length| length != parsed) {
+            return Err(anyhow::anyhow!("Conflicting Content-Length values"));
+        }
+        length = Some(parsed);
+    }
+    Ok(length)
+}
+
+/// Whether a comma-separated header such as `Connection` lists `token`.
+pub fn has_token(headers: &Headers, name: &str, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .flat_map(|value| value.split(','))
+        .any(|item| item.trim().eq_ignore_ascii_case(token))
+}
+
+/// Parse `Name: value` lines following a start line.
+pub fn parse_fields(lines: &[String]) -> Result<Headers> {
+    let mut headers = Headers::new();
+    for line in lines {
+        match line.split_once(':') {
+            Some((name, _)) if name.trim().is_empty() => {
+                return Err(anyhow::anyhow!("Empty header name"));
+            }
+            Some((name, value)) => headers.append(name.trim(), value.trim()),
+            None => return Err(anyhow::anyhow!("Invalid header format '{}'", line)),
+        }
+    }
+    Ok(headers)
+}
+
+/// Read a start line and header fields up to the blank line ending them, without
+/// line endings. Blank lines before the start line are skipped.
+pub async fn read_head<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut size = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        read_line(reader, &mut line, None).await?;
+        size += line.len();
+        if size > MAX_HEAD {
+            return Err(anyhow::anyhow!("Header block larger than {} KiB", MAX_HEAD / 1024));
+        }
+        let text = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string();
+        if text.is_empty() {
+            if lines.is_empty() {
+                continue;
+            }
+            return Ok(lines);
+        }
+        lines.push(text);
+    }
+}
+
+/// Copy one body from `reader` to `writer` unchanged. Returns the bytes copied.
+/// With `idle` set, a read or write that makes no progress for that long fails
+/// with a `Timeout` error.
+pub async fn relay<R, W>(reader: &mut R, writer: &mut W, framing: BodyFraming, idle: Option<Duration>) -> Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match framing {
+        BodyFraming::Empty => Ok(0),
+        BodyFraming::Length(length) => relay_exact(reader, writer, length, idle).await,
+        BodyFraming::Chunked => relay_chunked(reader, writer, u64::MAX, idle).await,
+        BodyFraming::UntilClose => {
+            let mut total = 0;
+            loop {
+                let buf = within(idle, reader.fill_buf()).await?;
+                if buf.is_empty() {
+                    return Ok(total);
+                }
+                let n = buf.len();
+                within(idle, writer.write_all(buf)).await?;
+                reader.consume(n);
+                total += n as u64;
+            }
+        }
+    }
+}
+
+/// Like `relay`, but fails with a `TooLarge` error once the body content passes
+/// `limit` bytes. Chunked bodies are checked before each chunk is sent on.
+pub async fn relay_limited<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    framing: BodyFraming,
+    limit: u64,
+    idle: Option<Duration>,
+) -> Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    match framing {
+        BodyFraming::Length(length) if length > limit => Err(too_large(limit)),
+        BodyFraming::Chunked => relay_chunked(reader, writer, limit, idle).await,
+        _ => relay(reader, writer, framing, idle).await,
+    }
+}
+
+fn too_large(limit: u64) -> anyhow::Error {
+    classify(ErrorKind::TooLarge, anyhow::anyhow!("Request body larger than {} bytes", limit))
+}
+
+// One read or write of a body, bounded by the idle timeout
+async fn within<T>(idle: Option<Duration>, io: impl Future<Output = std::io::Result<T>>) -> Result<T> {
+    match idle {
+        Some(idle) => match tokio::time::timeout(idle, io).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(classify(
+                ErrorKind::Timeout,
+                anyhow::anyhow!("Body stalled for {} seconds", idle.as_secs()),
+            )),
+        },
+        None => Ok(io.await?),
+    }
+}
+
+async fn relay_exact<R, W>(reader: &mut R, writer: &mut W, length: u64, idle: Option<Duration>) -> Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut remaining = length;
+    while remaining > 0 {
+        let buf = within(idle, reader.fill_buf()).await?;
+        if buf.is_empty() {
+            return Err(anyhow::anyhow!("Connection closed {} bytes before the end of the body", remaining));
+        }
+        let n = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        within(idle, writer.write_all(&buf[..n])).await?;
+        reader.consume(n);
+        remaining -= n as u64;
+    }
+    Ok(length)
+}
+
+// Chunk size lines, data and trailers pass through as received
+async fn relay_chunked<R, W>(reader: &mut R, writer: &mut W, limit: u64, idle: Option<Duration>) -> Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut total = 0;
+    let mut content: u64 = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        read_line(reader, &mut line, idle).await?;
+        let size = chunk_size(&line)?;
+        content = content.saturating_add(size);
+        if content > limit {
+            return Err(too_large(limit));
+        }
+        within(idle, writer.write_all(&line)).await?;
+        total += line.len() as u64;
+        if size == 0 {
+            break;
+        }
+        // Chunk data, then a CRLF and nothing else, so the backend cannot read
+        // the chunk boundary somewhere else
+        total += relay_exact(reader, writer, size, idle).await?;
+        line.clear();
+        read_line(reader, &mut line, idle).await?;
+        if line != b"\r\n" {
+            return Err(anyhow::anyhow!("Chunk data longer than its size line"));
+        }
+        within(idle, writer.write_all(&line)).await?;
+        total += line.len() as u64;
+    }
+    // Trailer fields end at an empty line
+    loop {
+        line.clear();
+        read_line(reader, &mut line, idle).await?;
+        require_crlf(&line)?;
+        within(idle, writer.write_all(&line)).await?;
+        total += line.len() as u64;
+        if line == b"\r\n" {
+            return Ok(total);
+        }
+    }
+}
+
+/// Copy only the content of a chunked body, for HTTP/1.0 clients that cannot
+/// read chunked encoding. Trailer fields are dropped. Returns the content bytes.
+pub async fn relay_dechunked<R, W>(reader: &mut R, writer: &mut W, idle: Option<Duration>) -> Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut total = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        read_line(reader, &mut line, idle).await?;
+        let size = chunk_size(&line)?;
+        if size == 0 {
+            break;
+        }
+        total += relay_exact(reader, writer, size, idle).await?;
+        line.clear();
+        read_line(reader, &mut line, idle).await?;
+        if line != b"\r\n" {
+            return Err(anyhow::anyhow!("Chunk data longer than its size line"));
+        }
+    }
+    loop {
+        line.clear();
+        read_line(reader, &mut line, idle).await?;
+        require_crlf(&line)?;
+        if line == b"\r\n" {
+            return Ok(total);
+        }
+    }
+}
+
+// Bare LF line endings are refused inside chunked bodies, so every hop frames
+// them the same way
+fn require_crlf(line: &[u8]) -> Result<()> {
+    if !line.ends_with(b"\r\n") {
+        return Err(anyhow::anyhow!("Chunked body line not ended by CRLF"));
+    }
+    Ok(())
+}
+
+fn chunk_size(line: &[u8]) -> Result<u64> {
+    require_crlf(line)?;
+    let line = String::from_utf8_lossy(&line[..line.len() - 2]);
+    let line: &str = &line;
+    // 1*HEXDIG, then optional whitespace only before an extension
+    let size = match line.split_once(';') {
+        Some((size, _)) => size.trim_end_matches([' ', '\t']),
+        None => line,
+    };
+    Some(size)
+        .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|size| u64::from_str_radix(size, 16).ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid chunk size '{}'", size))
+}
+
+// One line including its LF, at most MAX_LINE bytes
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, line: &mut Vec<u8>, idle: Option<Duration>) -> Result<()> {
+    let n = within(idle, (&mut *reader).take(MAX_LINE).read_until(b'\n', line)).await?;
+    if n == 0 {
+        return Err(anyhow::anyhow!("Connection closed in the middle of a message"));
+    }
+    if !line.ends_with(b"\n") {
+        if n as u64 == MAX_LINE {
+            return Err(anyhow::anyhow!("Line longer than {} KiB", MAX_LINE / 1024));
+        }
+        return Err(anyhow::anyhow!("Connection closed in the middle of a message"));
+    }
+    Ok(())
+}
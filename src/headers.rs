@@ -0,0 +1,78 @@
+// Ordered HTTP header fields that keep their casing and repeats
+
+/// Header fields in the order they arrived. Names keep the case the client
+/// sent and lookups ignore case. Repeated fields such as `Cookie` stay
+/// separate entries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Headers {
+        Headers::default()
+    }
+
+    /// Add a field after the existing ones, even if the name is already present.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_string(), value.to_string()));
+    }
+
+    /// Value of the first field with this name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Values of every field with this name, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Replace the value of the first field with this name, keeping its position
+    /// and casing, and drop any repeats. Appends the field if it is missing.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.fields.iter().position(|(field, _)| field.eq_ignore_ascii_case(name)) {
+            Some(first) => {
+                self.fields[first].1 = value.to_string();
+                let mut index = 0;
+                self.fields.retain(|(field, _)| {
+                    index += 1;
+                    index - 1 == first || !field.eq_ignore_ascii_case(name)
+                });
+            }
+            None => self.append(name, value),
+        }
+    }
+
+    /// Remove every field with this name.
+    pub fn remove(&mut self, name: &str) {
+        self.fields.retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The fields as `Name: value` lines, each ending in CRLF.
+    pub fn to_wire(&self) -> String {
+        self.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect()
+    }
+}
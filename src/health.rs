@@ -0,0 +1,212 @@
+// Active health checks for route backends
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use crate::access;
+use crate::balance::{Backend, BackendPool};
+use crate::cfg::Route;
+use crate::net::{connect_endpoint, Endpoint};
+use rsb::prelude::*;
+
+// Most bytes read from a backend while waiting for the `expect` pattern
+const MAX_EXPECT_READ: usize = 64 * 1024;
+
+/// The `[routes.health_check]` table.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HealthCheck {
+    #[serde(rename = "type", default = "default_check_type")]
+    pub check_type: String, // "tcp", "http" or "send-expect"
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_check_timeout_seconds")]
+    pub timeout_seconds: u64,
+    #[serde(default = "default_rise")]
+    pub rise: u32, // Consecutive passes before a down backend returns
+    #[serde(default = "default_fall")]
+    pub fall: u32, // Consecutive failures before a backend is taken out
+    #[serde(default = "default_check_path")]
+    pub path: String, // HTTP checks: request path
+    #[serde(default = "default_expect_status")]
+    pub expect_status: u16, // HTTP checks: required response status
+    #[serde(default)]
+    pub send: String, // send-expect checks: bytes written after connecting
+    #[serde(default)]
+    pub expect: String, // send-expect checks: bytes the reply must contain
+}
+
+fn default_check_type() -> String { "tcp".to_string() }
+fn default_interval_seconds() -> u64 { 10 }
+fn default_check_timeout_seconds() -> u64 { 2 }
+fn default_rise() -> u32 { 2 }
+fn default_fall() -> u32 { 3 }
+fn default_check_path() -> String { "/".to_string() }
+fn default_expect_status() -> u16 { 200 }
+
+#[derive(Debug, Clone)]
+enum Probe {
+    Tcp,
+    Http { path: String, expect_status: u16 },
+    SendExpect { send: Vec<u8>, expect: Vec<u8> },
+}
+
+impl HealthCheck {
+    pub fn validate(&self) -> Result<()> {
+        self.probe()?;
+        if self.interval_seconds == 0 || self.timeout_seconds == 0 || self.rise == 0 || self.fall == 0 {
+            return Err(anyhow::anyhow!("interval_seconds, timeout_seconds, rise and fall must be above zero"));
+        }
+        Ok(())
+    }
+
+    fn probe(&self) -> Result<Probe> {
+        match self.check_type.as_str() {
+            "tcp" => Ok(Probe::Tcp),
+            "http" => {
+                if !self.path.starts_with('/') {
+                    return Err(anyhow::anyhow!("Health check path must start with '/': {}", self.path));
+                }
+                Ok(Probe::Http { path: self.path.clone(), expect_status: self.expect_status })
+            }
+            "send-expect" => {
+                if self.expect.is_empty() {
+                    return Err(anyhow::anyhow!("send-expect health checks need a non-empty 'expect'"));
+                }
+                Ok(Probe::SendExpect {
+                    send: self.send.as_bytes().to_vec(),
+                    expect: self.expect.as_bytes().to_vec(),
+                })
+            }
+            other => Err(anyhow::anyhow!(
+                "Unknown health check type '{}' (expected tcp, http or send-expect)",
+                other
+            )),
+        }
+    }
+}
+
+/// Start one checker per backend of the pool if the route configures `health_check`.
+/// Checkers stop on their own once the pool is dropped.
+pub fn spawn_health_checks(route: &Route, pool: &Arc<BackendPool>) -> Result<()> {
+    let check = match &route.health_check {
+        Some(check) => check,
+        None => return Ok(()),
+    };
+    check.validate().context(format!("Invalid health_check on route '{}'", route.name))?;
+    let probe = check.probe()?;
+
+    for index in 0..pool.backends().len() {
+        tokio::spawn(check_backend(
+            route.name.clone(),
+            Arc::downgrade(pool),
+            index,
+            probe.clone(),
+            check.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn check_backend(
+    route_name: String,
+    pool: Weak<BackendPool>,
+    index: usize,
+    probe: Probe,
+    check: HealthCheck,
+) {
+    let interval = Duration::from_secs(check.interval_seconds);
+    let timeout = Duration::from_secs(check.timeout_seconds);
+    let mut passes = 0u32;
+    let mut failures = 0u32;
+
+    loop {
+        let backend: Arc<Backend> = match pool.upgrade() {
+            Some(pool) => pool.backends()[index].clone(),
+            None => return,
+        };
+
+        let result = match tokio::time::timeout(timeout, run_probe(&backend.endpoint, &probe)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", check.timeout_seconds)),
+        };
+
+        match result {
+            Ok(()) => {
+                failures = 0;
+                passes = passes.saturating_add(1);
+                if !backend.is_healthy() && passes >= check.rise {
+                    backend.set_healthy(true);
+                    access::notice(&format!("💚 [{}] Backend {} is healthy again", route_name, backend.endpoint));
+                }
+            }
+            Err(e) => {
+                passes = 0;
+                failures = failures.saturating_add(1);
+                if backend.is_healthy() && failures >= check.fall {
+                    backend.set_healthy(false);
+                    stderr!("💔 [{}] Backend {} is down: {}", route_name, backend.endpoint, e);
+                }
+            }
+        }
+
+        drop(backend);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+async fn run_probe(endpoint: &Endpoint, probe: &Probe) -> Result<()> {
+    let mut stream = connect_endpoint(endpoint).await?;
+
+    match probe {
+        Probe::Tcp => Ok(()),
+        Probe::Http { path, expect_status } => {
+            // host:port, with IPv6 literals in brackets
+            let host = match endpoint {
+                Endpoint::Tcp { .. } => endpoint.to_string(),
+                Endpoint::Unix(_) => "localhost".to_string(),
+            };
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: porty-health-check\r\nConnection: close\r\n\r\n",
+                path, host
+            );
+            stream.write_all(request.as_bytes()).await?;
+
+            let mut status_line = String::new();
+            BufReader::new(&mut stream).read_line(&mut status_line).await?;
+            let status: u16 = status_line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|code| code.parse().ok())
+                .ok_or_else(|| anyhow::anyhow!("invalid HTTP response '{}'", status_line.trim_end()))?;
+
+            if status != *expect_status {
+                return Err(anyhow::anyhow!("HTTP status {} (expected {})", status, expect_status));
+            }
+            Ok(())
+        }
+        Probe::SendExpect { send, expect } => {
+            if !send.is_empty() {
+                stream.write_all(send).await?;
+            }
+
+            let mut received = Vec::new();
+            let mut buffer = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut buffer).await?;
+                if n == 0 {
+                    return Err(anyhow::anyhow!("connection closed before expected reply"));
+                }
+                received.extend_from_slice(&buffer[..n]);
+                if received.windows(expect.len()).any(|window| window == expect.as_slice()) {
+                    return Ok(());
+                }
+                if received.len() > MAX_EXPECT_READ {
+                    return Err(anyhow::anyhow!("expected reply not found in {} bytes", received.len()));
+                }
+            }
+        }
+    }
+}
@@ -2,9 +2,22 @@
 
 use anyhow::{Context, Result};
 use chrono::Local;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
+use std::fmt;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use crate::access::{self, classify, error_kind, AccessRecord, ErrorKind, LogFormat};
+use crate::acl;
+use crate::balance::{client_ip, BalanceContext};
+use crate::cfg::Route;
+use crate::framing::{self, BodyFraming};
+use crate::headers::Headers;
+use crate::metrics::{self, RouteMetrics};
+use crate::net::{connect_backend, splice, BoxedStream, Endpoint};
+use crate::shutdown::ConnectionInfo;
+use std::sync::atomic::Ordering;
+use crate::vhost::VirtualHosts;
 use rsb::prelude::*;
 
 #[derive(Debug, Clone)]
@@ -12,150 +25,464 @@ pub struct HttpRequest {
     pub method: String,
     pub path: String,
     pub query: String,
-    pub headers: HashMap<String, String>,
-    pub body: Vec<u8>,
+    pub version: String, // "HTTP/1.1" or "HTTP/1.0"
+    pub headers: Headers,
+    pub body_framing: BodyFraming, // The body stays on the client stream and is relayed while forwarding
 }
 
-#[derive(Debug)]
+impl HttpRequest {
+    /// Whether the client lets the connection stay open after the response:
+    /// HTTP/1.1 unless it sent `Connection: close`, HTTP/1.0 only with `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        if framing::has_token(&self.headers, "Connection", "close") {
+            return false;
+        }
+        self.version.eq_ignore_ascii_case("HTTP/1.1") || framing::has_token(&self.headers, "Connection", "keep-alive")
+    }
+
+    /// Whether the client asks to switch protocols, as WebSocket handshakes do.
+    pub fn is_upgrade(&self) -> bool {
+        self.headers.contains("Upgrade") && framing::has_token(&self.headers, "Connection", "upgrade")
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DynamicRoute {
-    pub target_host: String,
-    pub target_port: u16,
+    pub target: Endpoint,
+    pub address: Option<SocketAddr>, // Checked address of a porty_host target, connected to instead of target
+}
+
+/// Path-prefix rule inside an HTTP route: requests under `prefix` go to `target`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PathRule {
+    pub prefix: String, // "/api" or "/api/*"
+    pub target: String, // "host:port" or "unix:/path"
+    #[serde(default)]
+    pub strip_prefix: bool, // Forward "/api/users" as "/users"
+}
+
+impl PathRule {
+    fn base(&self) -> &str {
+        self.prefix.trim_end_matches('*').trim_end_matches('/')
+    }
+
+    /// Prefixes match whole path segments: "/api" covers "/api" and "/api/users", not "/apix".
+    pub fn matches(&self, path: &str) -> bool {
+        let base = self.base();
+        base.is_empty() || path == base || (path.starts_with(base) && path[base.len()..].starts_with('/'))
+    }
+
+    pub fn rewrite(&self, path: &str) -> String {
+        if !self.strip_prefix {
+            return path.to_string();
+        }
+        match &path[self.base().len()..] {
+            "" => "/".to_string(),
+            rest => rest.to_string(),
+        }
+    }
+}
+
+/// Longest matching prefix wins.
+pub fn match_path_rule<'a>(rules: &'a [PathRule], path: &str) -> Option<&'a PathRule> {
+    rules.iter()
+        .filter(|rule| rule.matches(path))
+        .fold(None, |best: Option<&PathRule>, rule| match best {
+            Some(b) if b.base().len() >= rule.base().len() => Some(b),
+            _ => Some(rule),
+        })
+}
+
+/// The `host_header` setting: which Host header the backend receives.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum HostHeader {
+    Preserve, // The Host the client sent
+    #[default]
+    Target, // The backend's host:port
+    Custom(String), // "custom:<value>"
+}
+
+impl HostHeader {
+    pub fn parse(spec: &str) -> Result<HostHeader> {
+        match spec {
+            "preserve" => Ok(HostHeader::Preserve),
+            "target" => Ok(HostHeader::Target),
+            _ => match spec.strip_prefix("custom:") {
+                Some(value) if !value.is_empty() && !value.chars().any(|c| c.is_control() || c.is_whitespace()) => {
+                    Ok(HostHeader::Custom(value.to_string()))
+                }
+                _ => Err(anyhow::anyhow!(
+                    "Invalid host_header '{}' (expected preserve, target or custom:<value>)",
+                    spec
+                )),
+            },
+        }
+    }
+
+    /// The Host value for a request to `target`. Unix sockets have no host name,
+    /// so `target` keeps the client's Host there, or sends "localhost" without one;
+    /// `preserve` falls back the same way when the client sent no Host.
+    pub fn value(&self, target: &Endpoint, client_host: Option<&str>) -> String {
+        match (self, target, client_host) {
+            (HostHeader::Custom(value), _, _) => value.clone(),
+            (HostHeader::Preserve, _, Some(host)) | (HostHeader::Target, Endpoint::Unix(_), Some(host)) => host.to_string(),
+            (_, Endpoint::Tcp { .. }, _) => target.to_string(),
+            (_, Endpoint::Unix(_), _) => "localhost".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HostHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostHeader::Preserve => write!(f, "preserve"),
+            HostHeader::Target => write!(f, "target"),
+            HostHeader::Custom(value) => write!(f, "custom:{}", value),
+        }
+    }
+}
+
+impl TryFrom<String> for HostHeader {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: String) -> Result<HostHeader> {
+        HostHeader::parse(&spec)
+    }
+}
+
+impl From<HostHeader> for String {
+    fn from(policy: HostHeader) -> String {
+        policy.to_string()
+    }
 }
 
 #[derive(Debug)]
 pub struct ResponseInfo {
     pub status: String,
     pub body_size: usize,
+    pub keep_alive: bool, // The client connection can take another request
+}
+
+impl ResponseInfo {
+    /// Numeric code from a status line like "HTTP/1.1 200 OK".
+    pub fn status_code(&self) -> Option<u16> {
+        self.status.split_whitespace().nth(1)?.parse().ok()
+    }
 }
 
+// Copy buffer for each direction of an upgraded connection
+const UPGRADE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// How far a forwarding attempt got with the client's side of the exchange.
+#[derive(Debug, Default)]
+struct Progress {
+    body_read: bool, // The request body was taken off the client stream and cannot be resent
+    responded: bool, // Part of a response already reached the client
+}
+
+/// A client connection, buffered so pipelined requests stay queued between reads.
+type ClientStream = BufReader<BoxedStream>;
+
+/// Serve requests from one client connection until it closes, asks to close,
+/// idles past the keep-alive timeout or the server starts draining.
 pub async fn handle_http_connection(
-    mut client: TcpStream,
-    route_name: String,
-    default_target: String,
-    route_host: Option<String>,
+    client: BoxedStream,
+    connection: &ConnectionInfo,
+    vhosts: &VirtualHosts,
+    tls: bool, // The client came through the listener's TLS acceptor
     log_requests: bool,
+    log_format: LogFormat,
     verbose: bool,
-    log_level: &str,
-    timeout_seconds: u64,
-    max_retries: u32,
 ) -> Result<()> {
-    let client_addr = client.peer_addr()?.to_string();
-
-    // Parse HTTP request
-    let request = parse_http_request(&mut client).await?;
-
-    // Check for host header matching first (if configured)
-    let target_route = if let Some(expected_host) = &route_host {
-        if let Some(incoming_host) = request.headers.get("host") {
-            if incoming_host == expected_host {
-                // Host header matches, use configured route target
-                let parts: Vec<&str> = default_target.split(':').collect();
-                Some(DynamicRoute {
-                    target_host: parts[0].to_string(),
-                    target_port: parts.get(1)
-                        .and_then(|p| p.parse().ok())
-                        .unwrap_or(80),
-                })
-            } else {
-                // Host header doesn't match, check for dynamic routing fallback
-                extract_dynamic_route(&request.query)?
-            }
-        } else {
-            // No host header, check for dynamic routing fallback
-            extract_dynamic_route(&request.query)?
+    let mut client = BufReader::new(client);
+
+    // The first request must arrive within the listener's connection timeout
+    let mut idle_seconds = listener_timeout(vhosts);
+    loop {
+        let idle = std::time::Duration::from_secs(idle_seconds);
+        if !next_request(&mut client, connection, idle).await? {
+            return Ok(());
         }
-    } else {
-        // No host matching configured, check for dynamic routing
-        extract_dynamic_route(&request.query)?
+        match handle_http_request(&mut client, connection, vhosts, tls, log_requests, log_format, verbose).await? {
+            Some(keepalive_seconds) if keepalive_seconds > 0 => idle_seconds = keepalive_seconds,
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Timeout of the listener's default route, which applies until a route is picked.
+fn listener_timeout(vhosts: &VirtualHosts) -> u64 {
+    vhosts.default_route()
+        .or(vhosts.routes().first())
+        .map_or(30, |route| route.timeout_seconds)
+}
+
+/// Wait for the first byte of the next request. Returns `false` when the client
+/// closed the connection, stayed idle for `idle` or the server is draining.
+async fn next_request(client: &mut ClientStream, connection: &ConnectionInfo, idle: std::time::Duration) -> Result<bool> {
+    if connection.is_draining() {
+        return Ok(false);
+    }
+    tokio::select! {
+        result = tokio::time::timeout(idle, client.fill_buf()) => match result {
+            Ok(buf) => Ok(!buf?.is_empty()),
+            Err(_) => Ok(false),
+        },
+        _ = connection.draining() => Ok(false),
+    }
+}
+
+/// Serve one request. Returns the keep-alive timeout of the route that served it,
+/// or `None` when the connection must close after the response.
+async fn handle_http_request(
+    client: &mut ClientStream,
+    connection: &ConnectionInfo,
+    vhosts: &VirtualHosts,
+    tls: bool,
+    log_requests: bool,
+    log_format: LogFormat,
+    verbose: bool,
+) -> Result<Option<u64>> {
+    let client_addr = connection.client.as_str();
+    let structured = log_requests && log_format.is_structured();
+    let records = log_requests && access::wants_records(log_format, &connection.listener);
+    let request_start = std::time::Instant::now();
+    let bytes_at_start = (connection.bytes_in.load(Ordering::Relaxed), connection.bytes_out.load(Ordering::Relaxed));
+
+    // Until a virtual host is picked, records carry the listener's name
+    let mut record = AccessRecord::new(&connection.listener, client_addr);
+    let emit = |mut record: AccessRecord| {
+        record.bytes_in = connection.bytes_in.load(Ordering::Relaxed) - bytes_at_start.0;
+        record.bytes_out = connection.bytes_out.load(Ordering::Relaxed) - bytes_at_start.1;
+        record.emit(log_format, request_start.elapsed());
     };
 
-    // If no route determined, use default target
-    let dynamic_route = target_route.or_else(|| {
-        // Parse default target as fallback
-        let parts: Vec<&str> = default_target.split(':').collect();
-        if parts.len() == 2 {
-            Some(DynamicRoute {
-                target_host: parts[0].to_string(),
-                target_port: parts[1].parse().unwrap_or(80),
-            })
-        } else {
-            None
+    // The whole head must arrive within the timeout, so a client trickling it
+    // byte by byte cannot hold the connection; draining drops half-sent heads
+    let head_timeout = std::time::Duration::from_secs(listener_timeout(vhosts));
+    let parsed = tokio::select! {
+        parsed = tokio::time::timeout(head_timeout, parse_http_request(client)) => parsed,
+        _ = connection.draining() => return Ok(None),
+    };
+    let mut request = match parsed {
+        Ok(Ok(request)) => request,
+        Err(_) => {
+            let _ = send_error_response(client, 408, "Request head not received in time").await;
+            if records {
+                record.status = Some(408);
+                record.error = Some(ErrorKind::Timeout);
+                emit(record);
+            }
+            return Err(classify(ErrorKind::Timeout, anyhow::anyhow!("Timed out reading request head")));
         }
-    });
-
-    if let Some(route) = dynamic_route {
-        if log_requests && log_level != "none" {
+        Ok(Err(e)) => {
+            let _ = send_error_response(client, 400, "Malformed HTTP request").await;
+            if records {
+                record.status = Some(400);
+                record.error = Some(ErrorKind::BadRequest);
+                emit(record);
+            }
+            return Err(classify(ErrorKind::BadRequest, e));
+        }
+    };
+    record.method = Some(request.method.clone());
+    record.path = Some(request.path.clone());
+
+    // Pick the virtual host by Host header, falling back to the listener's default route
+    let selected = match vhosts.select(request.headers.get("host")) {
+        Some(selected) => selected,
+        None => {
+            let _ = send_error_response(client, 404, "No route for host").await;
+            if records {
+                record.status = Some(404);
+                record.error = Some(ErrorKind::NoRoute);
+                emit(record);
+            }
+            return Ok(None);
+        }
+    };
+    let route_config = selected.route;
+    let route_name = &route_config.name;
+    let metrics = metrics::route(route_name);
+    let log_level = route_config.log_level.as_str();
+    let structured = structured && log_level != "none";
+    let records = log_requests && log_level != "none" && access::wants_records(log_format, route_name);
+    record.route = route_name.clone();
+
+    if !acl::admits(route_config, client_addr) {
+        metrics.rejected_connections.fetch_add(1, Ordering::Relaxed);
+        metrics.record_status(403);
+        let _ = send_error_response(client, 403, "Forbidden").await;
+        if records {
+            record.status = Some(403);
+            record.error = Some(ErrorKind::Forbidden);
+            emit(record);
+        }
+        if !structured {
             let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-            echo!(
-                "🔄 [{}] {} | {} {}?{}",
-                route_name, timestamp, request.method, request.path, request.query
-            );
-            echo!("   ├─ From: {}", client_addr);
-            echo!(
-                "   ├─ To: {}:{} (dynamic)",
-                route.target_host, route.target_port
-            );
-            if log_level == "verbose" || verbose {
-                for (key, value) in &request.headers {
-                    echo!("   ├─ {}: {}", key, value);
-                }
+            stderr!("🚫 [{}] {} | Rejected {} by access list", route_name, timestamp, client_addr);
+        }
+        return Ok(None);
+    }
+
+    // Declared lengths over the limit are refused before anything is read; chunked
+    // bodies are counted while they stream
+    if let (Some(limit), BodyFraming::Length(length)) = (route_config.max_request_body, request.body_framing) {
+        if length > limit {
+            metrics.record_status(413);
+            let _ = send_error_response(client, 413, "Request body too large").await;
+            if records {
+                record.status = Some(413);
+                record.error = Some(ErrorKind::TooLarge);
+                emit(record);
             }
+            return Ok(None);
         }
+    }
 
-        let start_time = std::time::Instant::now();
+    let path_rule = match_path_rule(&route_config.paths, &request.path);
+
+    let mut target_route = if let Some(rule) = path_rule {
+        // Path rule matches, use its target
+        Some(DynamicRoute { target: Endpoint::parse(&rule.target)?, address: None })
+    } else if selected.host_matched || !route_config.dynamic.enabled {
+        // Host header matches (or dynamic routing is off), use configured route backends
+        None
+    } else {
+        // No host match, check for dynamic routing fallback
+        extract_dynamic_route(&request.query)?
+    };
 
-        // Forward the cleaned request with retry logic
-        match forward_http_request_with_retry(request, route, client, timeout_seconds, max_retries).await {
-            Ok(response_info) => {
-                if log_requests && log_level != "none" {
-                    let duration = start_time.elapsed();
+    // Dynamic targets must pass the route's policy before anything connects to them
+    if let Some(dynamic) = target_route.as_mut().filter(|_| path_rule.is_none()) {
+        match route_config.dynamic.authorize(&dynamic.target).await {
+            Ok(address) => dynamic.address = Some(address),
+            Err(e) => {
+                let kind = error_kind(&e);
+                let (status, message, icon) = match kind {
+                    ErrorKind::Forbidden => (403, "Dynamic target not allowed", "🚫"),
+                    _ => (502, "Could not resolve dynamic target", "❌"),
+                };
+                metrics.record_status(status);
+                let _ = send_error_response(client, status, message).await;
+                if records {
+                    record.target = Some(dynamic.target.to_string());
+                    record.status = Some(status);
+                    record.error = Some(kind);
+                    emit(record);
+                }
+                if !structured {
                     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-                    echo!(
-                        "✅ [{}] {} | {} ({:.0}ms)",
-                        route_name,
-                        timestamp,
-                        response_info.status,
-                        duration.as_millis()
-                    );
-                    if log_level == "verbose" || verbose {
-                        echo!("   └─ Body: {} bytes", response_info.body_size);
-                    }
+                    stderr!("{} [{}] {} | {} (client {})", icon, route_name, timestamp, e, client_addr);
                 }
+                return Ok(None);
             }
-            Err(e) => {
-                if log_requests {
-                    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-                    stderr!("❌ [{}] {} | Error: {}", route_name, timestamp, e);
+        }
+    }
+
+    // If no route determined, pick a backend from the route's pool. The lease
+    // counts this request as active on the backend until it is dropped.
+    let (route, _lease) = match target_route {
+        Some(route) => (route, None),
+        None => {
+            let balance_ctx = BalanceContext {
+                client_ip: client_ip(client_addr),
+                header_value: selected.pool.hash_header().and_then(|name| request.headers.get(name)),
+            };
+            match selected.pool.select(&balance_ctx) {
+                Some(lease) => (DynamicRoute { target: lease.endpoint().clone(), address: None }, Some(lease)),
+                None => {
+                    metrics.record_status(503);
+                    let _ = send_error_response(client, 503, "No healthy backend available").await;
+                    if records {
+                        record.status = Some(503);
+                        record.error = Some(ErrorKind::NoBackend);
+                        emit(record);
+                    }
+                    return Ok(None);
                 }
-                return Err(e);
             }
         }
-    } else {
-        // No dynamic routing, send 400 Bad Request
-        let _ = send_error_response(&mut client, 400, "Missing porty_host and porty_port parameters").await;
+    };
+    connection.set_target(&route.target);
+    record.target = Some(route.target.to_string());
+
+    if log_requests && log_level != "none" && !structured {
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+        echo!(
+            "🔄 [{}] {} | {} {}?{}",
+            route_name, timestamp, request.method, request.path, request.query
+        );
+        echo!("   ├─ From: {}", client_addr);
+        echo!("   ├─ To: {} (dynamic)", route.target);
+        if let Some(rule) = path_rule {
+            echo!("   ├─ Path rule: {}", rule.prefix);
+        }
+        if log_level == "verbose" || verbose {
+            for (key, value) in request.headers.iter() {
+                echo!("   ├─ {}: {}", key, value);
+            }
+        }
     }
 
-    Ok(())
-}
+    if let Some(rule) = path_rule {
+        request.path = rule.rewrite(&request.path);
+    }
 
-async fn parse_http_request(stream: &mut TcpStream) -> Result<HttpRequest> {
-    let mut reader = BufReader::new(stream);
-    let mut headers = HashMap::new();
-    let mut lines = Vec::new();
+    let proto = if tls { "https" } else { "http" };
+    route_config.forwarded.apply(&mut request.headers, client_addr, proto);
 
-    // Read request line and headers
-    loop {
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
-        if line == "\r\n" || line == "\n" {
-            break;
+    let start_time = std::time::Instant::now();
+
+    // Forward the cleaned request with retry logic
+    let result = forward_http_request_with_retry(request, route, client, route_config, &metrics, &mut record.retries).await;
+    metrics.request_duration.observe(start_time.elapsed());
+    let keep_alive = match result {
+        Ok(response_info) => {
+            metrics.record_status(response_info.status_code().unwrap_or(0));
+            if records {
+                record.status = response_info.status_code();
+                emit(record);
+            }
+            if log_requests && log_level != "none" && !structured {
+                let duration = start_time.elapsed();
+                let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+                echo!(
+                    "✅ [{}] {} | {} ({:.0}ms)",
+                    route_name,
+                    timestamp,
+                    response_info.status,
+                    duration.as_millis()
+                );
+                if log_level == "verbose" || verbose {
+                    echo!("   └─ Body: {} bytes", response_info.body_size);
+                }
+            }
+            response_info.keep_alive
         }
-        lines.push(line.trim_end().to_string());
-    }
+        Err(e) => {
+            let status = if error_kind(&e) == ErrorKind::TooLarge { 413 } else { 502 };
+            metrics.record_status(status);
+            if records {
+                record.status = Some(status);
+                record.error = Some(error_kind(&e));
+                emit(record);
+            }
+            if log_requests && !structured {
+                let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+                stderr!("❌ [{}] {} | Error: {}", route_name, timestamp, e);
+            }
+            return Err(e);
+        }
+    };
 
-    if lines.is_empty() {
-        return Err(anyhow::anyhow!("Malformed HTTP request: Empty request"));
-    }
+    Ok(keep_alive.then_some(route_config.keepalive_timeout_seconds))
+}
+
+async fn parse_http_request(reader: &mut ClientStream) -> Result<HttpRequest> {
+    // Read request line and headers
+    let lines = framing::read_head(reader).await?;
 
     // Parse request line: "GET /path?query HTTP/1.1"
     let request_line = &lines[0];
@@ -169,6 +496,7 @@ async fn parse_http_request(stream: &mut TcpStream) -> Result<HttpRequest> {
     }
 
     let method = parts[0].to_string();
+    let version = parts.get(2).unwrap_or(&"HTTP/1.0").to_string();
     let url_part = parts[1];
     let (path, query) = if let Some(pos) = url_part.find('?') {
         (url_part[..pos].to_string(), url_part[pos + 1..].to_string())
@@ -176,35 +504,19 @@ async fn parse_http_request(stream: &mut TcpStream) -> Result<HttpRequest> {
         (url_part.to_string(), String::new())
     };
 
-    // Parse headers
-    for line in &lines[1..] {
-        if let Some(pos) = line.find(':') {
-            let key = line[..pos].trim().to_lowercase();
-            let value = line[pos + 1..].trim().to_string();
-            if key.is_empty() {
-                return Err(anyhow::anyhow!("Malformed HTTP request: Empty header name"));
-            }
-            headers.insert(key, value);
-        } else if !line.trim().is_empty() {
-            return Err(anyhow::anyhow!("Malformed HTTP request: Invalid header format '{}'", line));
-        }
-    }
+    let headers = framing::parse_fields(&lines[1..])
+        .map_err(|e| anyhow::anyhow!("Malformed HTTP request: {}", e))?;
 
-    // Read body if Content-Length is specified
-    let mut body = Vec::new();
-    if let Some(content_length_str) = headers.get("content-length") {
-        if let Ok(content_length) = content_length_str.parse::<usize>() {
-            body.resize(content_length, 0);
-            reader.read_exact(&mut body).await?;
-        }
-    }
+    let body_framing = BodyFraming::for_request(&headers)
+        .map_err(|e| anyhow::anyhow!("Malformed HTTP request: {}", e))?;
 
     Ok(HttpRequest {
         method,
         path,
         query,
+        version,
         headers,
-        body,
+        body_framing,
     })
 }
 
@@ -222,8 +534,8 @@ fn extract_dynamic_route(query: &str) -> Result<Option<DynamicRoute>> {
     if let (Some(host), Some(port_str)) = (params.get("porty_host"), params.get("porty_port")) {
         if let Ok(port) = port_str.parse::<u16>() {
             return Ok(Some(DynamicRoute {
-                target_host: host.clone(),
-                target_port: port,
+                target: Endpoint::tcp(host, port),
+                address: None,
             }));
         }
     }
@@ -242,21 +554,33 @@ fn clean_query_string(query: &str) -> String {
 async fn forward_http_request_with_retry(
     request: HttpRequest,
     route: DynamicRoute,
-    mut client: TcpStream,
-    timeout_seconds: u64,
-    max_retries: u32,
+    client: &mut ClientStream,
+    route_config: &Route,
+    metrics: &RouteMetrics,
+    retries: &mut u32,
 ) -> Result<ResponseInfo> {
+    let max_retries = route_config.max_retries;
     let mut last_error = None;
+    let mut progress = Progress::default();
 
     for attempt in 0..=max_retries {
         if attempt > 0 {
             // Wait before retry (exponential backoff)
             let delay = std::time::Duration::from_millis(100 * (1u64 << (attempt - 1)));
             tokio::time::sleep(delay).await;
+            metrics.retries.fetch_add(1, Ordering::Relaxed);
+            *retries += 1;
         }
 
-        match forward_http_request_with_client(request.clone(), route.clone(), &mut client, timeout_seconds).await {
+        match forward_http_request_internal(request.clone(), route.clone(), client, route_config, metrics, &mut progress).await {
             Ok(response) => return Ok(response),
+            // Part of a response already reached the client, so neither a retry nor an error page fits
+            Err(e) if progress.responded => return Err(e),
+            // The request body is gone, so it cannot be sent again
+            Err(e) if progress.body_read => {
+                last_error = Some(e);
+                break;
+            }
             Err(e) => {
                 last_error = Some(e);
                 if attempt < max_retries {
@@ -267,43 +591,63 @@ async fn forward_http_request_with_retry(
     }
 
     // Send error response to client after all retries failed
-    let _ = send_error_response(&mut client, 502, "Backend connection failed after retries").await;
-
-    Err(last_error.unwrap())
-}
-
-impl Clone for DynamicRoute {
-    fn clone(&self) -> Self {
-        Self {
-            target_host: self.target_host.clone(),
-            target_port: self.target_port,
-        }
+    let last_error = last_error.unwrap();
+    if error_kind(&last_error) == ErrorKind::TooLarge {
+        let _ = send_error_response(client, 413, "Request body too large").await;
+    } else {
+        let _ = send_error_response(client, 502, "Backend connection failed after retries").await;
     }
-}
 
+    Err(last_error)
+}
 
-async fn forward_http_request_with_client(
-    request: HttpRequest,
-    route: DynamicRoute,
-    client: &mut TcpStream,
+// Bound a wait on the backend by the route's timeout, counting expiries
+async fn backend_timeout<T>(
     timeout_seconds: u64,
-) -> Result<ResponseInfo> {
-    let timeout = std::time::Duration::from_secs(timeout_seconds);
+    metrics: &RouteMetrics,
+    future: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::time::timeout(std::time::Duration::from_secs(timeout_seconds), future).await
+        .map_err(|_| {
+            metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+            classify(ErrorKind::Timeout, anyhow::anyhow!("Request timeout after {} seconds", timeout_seconds))
+        })?
+}
 
-    tokio::time::timeout(timeout, forward_http_request_internal(request, route, client)).await
-        .map_err(|_| anyhow::anyhow!("Request timeout after {} seconds", timeout_seconds))?
+// A body that stalls past the idle timeout counts like any other timeout
+fn count_timeout(metrics: &RouteMetrics, e: &anyhow::Error) {
+    if error_kind(e) == ErrorKind::Timeout {
+        metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 async fn forward_http_request_internal(
     request: HttpRequest,
     route: DynamicRoute,
-    client: &mut TcpStream,
+    client: &mut ClientStream,
+    route_config: &Route,
+    metrics: &RouteMetrics,
+    progress: &mut Progress,
 ) -> Result<ResponseInfo> {
-    // Connect to target
-    let target_addr = format!("{}:{}", route.target_host, route.target_port);
-    let mut target = TcpStream::connect(&target_addr)
+    // Connecting and waiting for the response head are bounded by timeout_seconds;
+    // bodies stream for as long as both sides keep them moving, each read and
+    // write again bounded by timeout_seconds
+    let timeout_seconds = route_config.timeout_seconds;
+    let idle = Some(timeout_seconds).filter(|seconds| *seconds > 0).map(std::time::Duration::from_secs);
+
+    // Connect to target, or to the address a dynamic target was checked against
+    let endpoint = match route.address {
+        Some(address) => Endpoint::tcp(&address.ip().to_string(), address.port()),
+        None => route.target.clone(),
+    };
+    let target = backend_timeout(timeout_seconds, metrics, connect_backend(&endpoint, metrics))
         .await
-        .with_context(|| format!("Failed to connect to {}", target_addr))?;
+        .with_context(|| format!("Failed to connect to {}", route.target))?;
+    let mut target = BufReader::new(target);
+
+    let keep_alive = request.keep_alive();
+    let upgrade = request.is_upgrade();
+    let http_10 = !request.version.eq_ignore_ascii_case("HTTP/1.1");
 
     // Clean the query string (remove porty_* params)
     let clean_query = clean_query_string(&request.query);
@@ -316,66 +660,134 @@ async fn forward_http_request_internal(
     // Build HTTP request
     let mut http_request = format!("{} {} HTTP/1.1\r\n", request.method, url_path);
 
-    // Add headers (update Host header to target)
+    // Add headers in client order, with Host set by the route's host_header policy
     let mut headers = request.headers;
-    headers.insert("host".to_string(), route.target_host);
-
-    for (key, value) in &headers {
-        http_request.push_str(&format!("{}: {}\r\n", key, value));
+    let host = route_config.host_header.value(&route.target, headers.get("Host"));
+    headers.set("Host", &host);
+
+    // Clients that sent `Expect: 100-continue` hold their body back until told to go on.
+    // Porty tells them once the backend is connected, so the backend must not answer too.
+    let expect_continue = request.body_framing != BodyFraming::Empty
+        && !http_10
+        && framing::has_token(&headers, "Expect", "100-continue");
+    if expect_continue {
+        headers.remove("Expect");
     }
 
+    http_request.push_str(&headers.to_wire());
     http_request.push_str("\r\n");
 
     // Send request
     target.write_all(http_request.as_bytes()).await?;
-    if !request.body.is_empty() {
-        target.write_all(&request.body).await?;
+    let mut request_size = http_request.len() as u64;
+    if expect_continue {
+        client.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+        client.flush().await?;
     }
+    if request.body_framing != BodyFraming::Empty {
+        // The body passes through as it arrives; chunk lines and trailers unchanged
+        progress.body_read = true;
+        let limit = route_config.max_request_body.unwrap_or(u64::MAX);
+        request_size += framing::relay_limited(client, &mut target, request.body_framing, limit, idle)
+            .await
+            .inspect_err(|e| count_timeout(metrics, e))?;
+    }
+    target.flush().await?;
+    metrics.bytes_received.fetch_add(request_size, Ordering::Relaxed);
+
+    // Interim 1xx responses pass through ahead of the final one
+    let (status_line, code, mut response_headers) = loop {
+        let lines = backend_timeout(timeout_seconds, metrics, framing::read_head(&mut target))
+            .await
+            .context("Backend sent no valid response")?;
+        let status_line = lines[0].clone();
+        let code: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid status line from backend '{}'", status_line))?;
+        let response_headers = framing::parse_fields(&lines[1..])
+            .map_err(|e| anyhow::anyhow!("Invalid response from backend: {}", e))?;
+        if (100..200).contains(&code) && code != 101 {
+            let interim = format!("{}\r\n{}\r\n", status_line, response_headers.to_wire());
+            progress.responded = true;
+            client.write_all(interim.as_bytes()).await?;
+            client.flush().await?;
+            metrics.bytes_sent.fetch_add(interim.len() as u64, Ordering::Relaxed);
+            continue;
+        }
+        break (status_line, code, response_headers);
+    };
 
-    // Read response and forward to client
-    let mut response_buf = Vec::new();
-    let mut temp_buf = [0u8; 8192];
-    let mut total_bytes = 0;
-    let mut status_line = String::new();
-    let mut headers_read = false;
-
-    loop {
-        match target.read(&mut temp_buf).await {
-            Ok(0) => break, // Connection closed
-            Ok(n) => {
-                response_buf.extend_from_slice(&temp_buf[..n]);
-                client.write_all(&temp_buf[..n]).await?;
-                total_bytes += n;
-
-                // Extract status line from first response
-                if !headers_read && !response_buf.is_empty() {
-                    let response_str = String::from_utf8_lossy(&response_buf);
-                    if let Some(first_line_end) = response_str.find('\n') {
-                        status_line = response_str[..first_line_end].trim().to_string();
-                        if response_str.contains("\r\n\r\n") {
-                            headers_read = true;
-                        }
-                    }
-                }
-            }
-            Err(e) => return Err(e.into()),
+    if code == 101 {
+        if !upgrade {
+            return Err(anyhow::anyhow!("Backend switched protocols without an Upgrade request"));
         }
+        // From here on the connection carries the new protocol both ways. Bytes either
+        // side sent early are still in the buffers and go first.
+        let head = format!("{}\r\n{}\r\n", status_line, response_headers.to_wire());
+        progress.responded = true;
+        client.write_all(head.as_bytes()).await?;
+        client.flush().await?;
+        metrics.bytes_sent.fetch_add(head.len() as u64, Ordering::Relaxed);
+        let idle = Some(route_config.upgrade_timeout_seconds)
+            .filter(|seconds| *seconds > 0)
+            .map(std::time::Duration::from_secs);
+        // Going idle ends the tunnel like a close does; splice counts it as a timeout
+        let _ = splice(&mut *client, &mut target, UPGRADE_BUFFER_SIZE, metrics, idle).await;
+        return Ok(ResponseInfo { status: status_line, body_size: 0, keep_alive: false });
     }
 
+    let body_framing = BodyFraming::for_response(&request.method, code, &response_headers)?;
+    // Chunked framing overrides a Content-Length, which must not reach the client (RFC 9112 §6.3)
+    if body_framing == BodyFraming::Chunked {
+        response_headers.remove("Content-Length");
+    }
+
+    // HTTP/1.0 clients cannot read chunked bodies: they get the bare content, ended by closing
+    let dechunk = http_10 && body_framing == BodyFraming::Chunked;
+    if dechunk {
+        response_headers.remove("Transfer-Encoding");
+        response_headers.remove("Trailer");
+    }
+
+    // A body that ends when the backend closes can only be relayed once per client connection
+    let keep_alive = keep_alive && body_framing != BodyFraming::UntilClose && !dechunk;
+    if !keep_alive {
+        response_headers.set("Connection", "close");
+    } else if http_10 {
+        response_headers.set("Connection", "keep-alive");
+    } else if framing::has_token(&response_headers, "Connection", "close") {
+        // The backend connection ends here, the client's does not
+        response_headers.remove("Connection");
+    }
+
+    let head = format!("{}\r\n{}\r\n", status_line, response_headers.to_wire());
+    progress.responded = true;
+    client.write_all(head.as_bytes()).await?;
+    let body_size = if dechunk {
+        framing::relay_dechunked(&mut target, client, idle).await
+    } else {
+        framing::relay(&mut target, client, body_framing, idle).await
+    }
+    .inspect_err(|e| count_timeout(metrics, e))?;
+    client.flush().await?;
+    metrics.bytes_sent.fetch_add(head.len() as u64 + body_size, Ordering::Relaxed);
+
     Ok(ResponseInfo {
-        status: if status_line.is_empty() {
-            "Unknown".to_string()
-        } else {
-            status_line
-        },
-        body_size: total_bytes,
+        status: status_line,
+        body_size: body_size as usize,
+        keep_alive,
     })
 }
 
-async fn send_error_response(client: &mut TcpStream, status_code: u16, message: &str) -> Result<()> {
+async fn send_error_response(client: &mut ClientStream, status_code: u16, message: &str) -> Result<()> {
     let status_text = match status_code {
         400 => "Bad Request",
+        403 => "Forbidden",
         404 => "Not Found",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
         500 => "Internal Server Error",
         502 => "Bad Gateway",
         503 => "Service Unavailable",
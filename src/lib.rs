@@ -1,9 +1,27 @@
 // Porty library modules
 
+pub mod access;
+pub mod acl;
+pub mod admin;
+pub mod balance;
 pub mod cfg;
+pub mod dynamic;
+pub mod forwarded;
+pub mod framing;
+pub mod headers;
+pub mod health;
 pub mod http;
+pub mod logfile;
+pub mod metrics;
 pub mod net;
+pub mod proxy_protocol;
+pub mod reload;
+pub mod shutdown;
+pub mod tls;
+pub mod udp;
+pub mod vhost;
 
 pub use cfg::{Config, Route, generate_example_config, load_config};
 pub use http::handle_http_connection;
-pub use net::{run_route, run_porty_server, format_bytes};
\ No newline at end of file
+pub use net::{run_route, run_listener, run_porty_server, format_bytes, Endpoint};
+pub use udp::run_udp_route;
\ No newline at end of file
@@ -0,0 +1,366 @@
+// Log files: access and error logs with size and time based rotation
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::time::Duration;
+use crate::access;
+use rsb::prelude::*;
+
+// How often files are checked for rotation between writes. The error log is
+// written by stderr directly, so this is the only place its size is noticed.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// Lines waiting for the writer thread. Callers only block once it falls this far behind.
+const QUEUE_LINES: usize = 8192;
+
+/// The `[log_rotation]` table, shared by every access and error log file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LogRotation {
+    #[serde(default)]
+    pub max_size_mb: u64, // Rotate once a file reaches this size; 0 disables
+    #[serde(default = "default_interval")]
+    pub interval: String, // "never", "hourly" or "daily"
+    #[serde(default = "default_keep")]
+    pub keep: usize, // Rotated files kept as <path>.1 (newest) to <path>.<keep>
+}
+
+fn default_interval() -> String { "never".to_string() }
+fn default_keep() -> usize { 7 }
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        LogRotation {
+            max_size_mb: 0,
+            interval: default_interval(),
+            keep: default_keep(),
+        }
+    }
+}
+
+impl LogRotation {
+    pub fn validate(&self) -> Result<()> {
+        self.period_format().map(|_| ())
+    }
+
+    // chrono format naming the current rotation period
+    fn period_format(&self) -> Result<Option<&'static str>> {
+        match self.interval.as_str() {
+            "never" => Ok(None),
+            "hourly" => Ok(Some("%Y-%m-%d %H")),
+            "daily" => Ok(Some("%Y-%m-%d")),
+            other => Err(anyhow::anyhow!(
+                "Unknown log_rotation interval '{}' (expected never, hourly or daily)",
+                other
+            )),
+        }
+    }
+
+    fn current_period(&self) -> Option<String> {
+        self.period_format().ok().flatten().map(|format| Local::now().format(format).to_string())
+    }
+}
+
+/// An append-only log file. Writes and rotation share one lock, so a line is
+/// never split across files.
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    state: Mutex<FileState>,
+}
+
+#[derive(Debug)]
+struct FileState {
+    file: File,
+    size: u64,
+    period: Option<String>, // Rotation period of the first write
+    stderr: bool,           // Installed as the process's stderr
+}
+
+fn registry() -> &'static Mutex<HashMap<PathBuf, Weak<LogFile>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<PathBuf, Weak<LogFile>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn rotation() -> &'static RwLock<LogRotation> {
+    static ROTATION: OnceLock<RwLock<LogRotation>> = OnceLock::new();
+    ROTATION.get_or_init(|| RwLock::new(LogRotation::default()))
+}
+
+/// Replace the rotation settings used by every log file.
+pub fn set_rotation(settings: LogRotation) {
+    *rotation().write().unwrap() = settings;
+}
+
+impl LogFile {
+    /// Open a log file for appending. Every caller naming the same path shares
+    /// one handle, so reloads keep writing to the file already open.
+    pub fn open(path: &str) -> Result<Arc<LogFile>> {
+        let path = PathBuf::from(path);
+        let mut files = registry().lock().unwrap();
+        if let Some(file) = files.get(&path).and_then(Weak::upgrade) {
+            return Ok(file);
+        }
+
+        let (file, size) = open_append(&path)?;
+        let log_file = Arc::new(LogFile {
+            path: path.clone(),
+            state: Mutex::new(FileState { file, size, period: None, stderr: false }),
+        });
+        files.retain(|_, file| file.strong_count() > 0);
+        files.insert(path, Arc::downgrade(&log_file));
+        Ok(log_file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue one line for the writer thread, so a slow disk never blocks the
+    /// runtime's workers. Lines reach the file in the order they were queued.
+    pub fn write_line(self: &Arc<Self>, line: &str) {
+        let job = WriteJob::Line(self.clone(), format!("{}\n", line));
+        if writer().send(job).is_err() {
+            log::warn!("[log] {}: writer thread is gone", self.path.display());
+        }
+    }
+
+    // Append one line, rotating first if the file is due. Runs on the writer thread.
+    fn append(&self, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        let result = self
+            .rotate_if_due(&mut state, line.len() as u64)
+            .and_then(|_| state.file.write_all(line.as_bytes()).context("Write failed"));
+        match result {
+            Ok(()) => state.size += line.len() as u64,
+            Err(e) => log::warn!("[log] {}: {:#}", self.path.display(), e),
+        }
+    }
+
+    /// Open the path again, for after logrotate has moved the file away.
+    pub fn reopen(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.reopen_locked(&mut state)
+    }
+
+    /// Rotate if the size limit or the rotation period has been passed.
+    pub fn check(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Ok(meta) = state.file.metadata() {
+            state.size = meta.len();
+        }
+        self.rotate_if_due(&mut state, 0)
+    }
+
+    fn rotate_if_due(&self, state: &mut FileState, incoming: u64) -> Result<()> {
+        let settings = rotation().read().unwrap().clone();
+        let max_size = settings.max_size_mb * 1024 * 1024;
+        let oversized = max_size > 0 && state.size > 0 && state.size + incoming > max_size;
+
+        let period = settings.current_period();
+        let expired = state.period.is_some() && period.is_some() && state.period != period;
+        if state.period.is_none() {
+            state.period = period;
+        }
+
+        if oversized || expired {
+            self.rotate(state, settings.keep)?;
+        }
+        Ok(())
+    }
+
+    // Shift <path>.1 .. <path>.<keep-1> up by one, move the live file to
+    // <path>.1 and start a new one. The oldest file is overwritten.
+    fn rotate(&self, state: &mut FileState, keep: usize) -> Result<()> {
+        if self.path.exists() {
+            if keep == 0 {
+                fs::remove_file(&self.path)
+                    .context(format!("Failed to remove {}", self.path.display()))?;
+            } else {
+                for n in (1..keep).rev() {
+                    let from = numbered(&self.path, n);
+                    if from.exists() {
+                        fs::rename(&from, numbered(&self.path, n + 1))
+                            .context(format!("Failed to rotate {}", from.display()))?;
+                    }
+                }
+                fs::rename(&self.path, numbered(&self.path, 1))
+                    .context(format!("Failed to rotate {}", self.path.display()))?;
+            }
+        }
+        self.reopen_locked(state)?;
+        state.period = rotation().read().unwrap().current_period();
+        Ok(())
+    }
+
+    fn reopen_locked(&self, state: &mut FileState) -> Result<()> {
+        let (file, size) = open_append(&self.path)?;
+        if state.stderr {
+            redirect_stderr(&file)?;
+        }
+        state.file = file;
+        state.size = size;
+        Ok(())
+    }
+}
+
+enum WriteJob {
+    Line(Arc<LogFile>, String),
+    Flush(SyncSender<()>),
+}
+
+fn writer() -> &'static SyncSender<WriteJob> {
+    static WRITER: OnceLock<SyncSender<WriteJob>> = OnceLock::new();
+    WRITER.get_or_init(|| {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_LINES);
+        std::thread::Builder::new()
+            .name("porty-log-writer".to_string())
+            .spawn(move || run_writer(receiver))
+            .expect("failed to start the log writer thread");
+        sender
+    })
+}
+
+fn run_writer(jobs: Receiver<WriteJob>) {
+    for job in jobs {
+        match job {
+            WriteJob::Line(file, line) => file.append(&line),
+            WriteJob::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// Block until every line queued so far is written, for before the process exits.
+pub fn flush() {
+    let (done, wait) = mpsc::sync_channel(1);
+    if writer().send(WriteJob::Flush(done)).is_ok() {
+        let _ = wait.recv();
+    }
+}
+
+fn open_append(path: &Path) -> Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open log file {}", path.display()))?;
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    Ok((file, size))
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn open_files() -> Vec<Arc<LogFile>> {
+    registry().lock().unwrap().values().filter_map(Weak::upgrade).collect()
+}
+
+/// Reopen every log file on SIGUSR1 and rotate them on schedule.
+pub async fn watch_log_files() -> Result<()> {
+    let mut poll = tokio::time::interval(CHECK_INTERVAL);
+
+    #[cfg(unix)]
+    let mut user1 = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?;
+
+    loop {
+        #[cfg(unix)]
+        let signalled = tokio::select! {
+            _ = user1.recv() => true,
+            _ = poll.tick() => false,
+        };
+        #[cfg(not(unix))]
+        let signalled = {
+            poll.tick().await;
+            false
+        };
+
+        let files = open_files();
+        if signalled {
+            access::notice(&format!("🔁 SIGUSR1 received, reopening {} log file(s)", files.len()));
+        }
+        for file in files {
+            let result = if signalled { file.reopen() } else { file.check() };
+            if let Err(e) = result {
+                stderr!("❌ Log file {}: {:#}", file.path().display(), e);
+            }
+        }
+    }
+}
+
+fn error_log() -> &'static Mutex<Option<Arc<LogFile>>> {
+    static ERROR_LOG: OnceLock<Mutex<Option<Arc<LogFile>>>> = OnceLock::new();
+    ERROR_LOG.get_or_init(|| Mutex::new(None))
+}
+
+/// Send stderr (errors, warnings and log messages) to `file`, or back to the
+/// original stderr when it is None.
+pub fn set_error_log(file: Option<Arc<LogFile>>) -> Result<()> {
+    let mut current = error_log().lock().unwrap();
+    if current.as_ref().map(|f| f.path()) == file.as_ref().map(|f| f.path()) {
+        return Ok(());
+    }
+
+    match &file {
+        Some(file) => {
+            let mut state = file.state.lock().unwrap();
+            redirect_stderr(&state.file)?;
+            state.stderr = true;
+        }
+        None => restore_stderr()?,
+    }
+    if let Some(previous) = current.take() {
+        previous.state.lock().unwrap().stderr = false;
+    }
+    *current = file;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn original_stderr() -> &'static OnceLock<std::os::fd::OwnedFd> {
+    static ORIGINAL: OnceLock<std::os::fd::OwnedFd> = OnceLock::new();
+    &ORIGINAL
+}
+
+#[cfg(unix)]
+fn redirect_stderr(file: &impl std::os::fd::AsRawFd) -> Result<()> {
+    use std::os::fd::AsFd;
+
+    if original_stderr().get().is_none() {
+        let original = std::io::stderr().as_fd().try_clone_to_owned().context("Failed to save stderr")?;
+        let _ = original_stderr().set(original);
+    }
+    // SAFETY: dup2 only reads both descriptors, which stay open for the call
+    if unsafe { libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to redirect stderr");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restore_stderr() -> Result<()> {
+    match original_stderr().get() {
+        Some(original) => redirect_stderr(original),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn redirect_stderr(_file: &File) -> Result<()> {
+    Err(anyhow::anyhow!("error_log is only supported on Unix"))
+}
+
+#[cfg(not(unix))]
+fn restore_stderr() -> Result<()> {
+    Ok(())
+}
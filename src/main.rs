@@ -3,6 +3,9 @@
 
 use anyhow::Result;
 use porty::{load_config, generate_example_config, run_porty_server};
+use porty::access::{self, LogFormat};
+use porty::logfile;
+use porty::shutdown::{DrainTimeout, EXIT_DRAIN_TIMEOUT};
 use rsb::prelude::*;
 
 fn main() {
@@ -37,9 +40,16 @@ fn main() {
 }
 
 fn cmd_start(args: Args) -> i32 {
-    match run_async_start(args) {
+    let result = run_async_start(args);
+    // Access records still queued for log files go out before exit
+    logfile::flush();
+    match result {
         Ok(_) => 0,
         Err(e) => {
+            if let Some(timeout) = e.downcast_ref::<DrainTimeout>() {
+                stderr!("⚠️  Shutdown incomplete: {}", timeout);
+                return EXIT_DRAIN_TIMEOUT;
+            }
             stderr!("❌ Failed to start: {}", e);
             1
         }
@@ -102,21 +112,11 @@ async fn run_async_start(_args: Args) -> Result<()> {
         "config.toml".to_string()
     };
 
-    let mut config = load_config(&std::path::PathBuf::from(config_path.clone()))?;
+    let config_path = std::path::PathBuf::from(config_path);
+    let mut config = load_config(&config_path)?;
 
     // Override config with global context (RSB pattern)
-    if has_var("opt_listen_port") {
-        config.listen_port = get_var("opt_listen_port").parse().unwrap_or(config.listen_port);
-    }
-    if has_var("opt_target_port") {
-        config.target_port = get_var("opt_target_port").parse().unwrap_or(config.target_port);
-    }
-    if has_var("opt_listen_addr") {
-        config.listen_addr = get_var("opt_listen_addr");
-    }
-    if has_var("opt_target_addr") {
-        config.target_addr = get_var("opt_target_addr");
-    }
+    config.apply_overrides();
 
     // Daemonize if requested (Unix only)
     #[cfg(unix)]
@@ -124,14 +124,17 @@ async fn run_async_start(_args: Args) -> Result<()> {
         daemonize()?;
     }
 
-    // Print startup message
-    echo!("🚀 Porty v{} starting up", env!("CARGO_PKG_VERSION"));
-    echo!("📁 Config loaded from: {}", config_path);
-    echo!("🔊 Main route: {}:{} -> {}:{}",
+    // Print startup message, on stderr when stdout is kept for JSON or logfmt records
+    if let Ok(format) = LogFormat::parse(&config.log_format) {
+        access::set_stdout_format(format, config.log_requests);
+    }
+    access::notice(&format!("🚀 Porty v{} starting up", env!("CARGO_PKG_VERSION")));
+    access::notice(&format!("📁 Config loaded from: {}", config_path.display()));
+    access::notice(&format!("🔊 Main route: {}:{} -> {}:{}",
         config.listen_addr, config.listen_port,
-        config.target_addr, config.target_port);
+        config.target_addr, config.target_port));
 
-    run_porty_server(config).await
+    run_porty_server(config, config_path).await
 }
 
 #[cfg(unix)]
@@ -0,0 +1,240 @@
+// Prometheus metrics: per-route counters and latency histograms
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use crate::net::{Endpoint, Listener};
+
+// Upper bounds in seconds, shared by every latency histogram
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+// Time a scraper gets to send its request line
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Counters for one route. Stream connections are counted per listener, so
+/// routes sharing a port appear as "a+b"; HTTP requests use the route name.
+#[derive(Debug, Default)]
+pub struct RouteMetrics {
+    pub connections: AtomicU64,
+    pub active_connections: AtomicI64,
+    pub bytes_received: AtomicU64, // client -> backend
+    pub bytes_sent: AtomicU64,     // backend -> client
+    pub http_responses: [AtomicU64; STATUS_CLASSES.len()],
+    pub retries: AtomicU64,
+    pub connect_failures: AtomicU64,
+    pub timeouts: AtomicU64,
+    pub rejected_connections: AtomicU64, // Refused by allow/deny lists or the UDP session limit
+    pub request_duration: Histogram,
+    pub connect_duration: Histogram,
+}
+
+impl RouteMetrics {
+    /// Count an accepted connection; it stays active until the guard drops.
+    pub fn connection_opened(self: &Arc<Self>) -> ActiveConnection {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ActiveConnection { metrics: self.clone() }
+    }
+
+    pub fn record_status(&self, status: u16) {
+        if let Some(counter) = self.http_responses.get((status / 100).wrapping_sub(1) as usize) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct ActiveConnection {
+    metrics: Arc<RouteMetrics>,
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.metrics.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn registry() -> &'static Mutex<BTreeMap<String, Arc<RouteMetrics>>> {
+    static REGISTRY: OnceLock<Mutex<BTreeMap<String, Arc<RouteMetrics>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Metrics for a route, created on first use. Series survive config reloads.
+pub fn route(name: &str) -> Arc<RouteMetrics> {
+    registry()
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Metrics for a route if it has recorded anything yet.
+pub fn get(name: &str) -> Option<Arc<RouteMetrics>> {
+    registry().lock().unwrap().get(name).cloned()
+}
+
+/// Everything in the Prometheus text exposition format.
+pub fn render() -> String {
+    let routes: Vec<(String, Arc<RouteMetrics>)> = registry()
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, metrics)| (escape_label(name), metrics.clone()))
+        .collect();
+    let mut out = String::new();
+
+    let counter = |out: &mut String, name: &str, help: &str, value: fn(&RouteMetrics) -> &AtomicU64| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+        for (route, metrics) in &routes {
+            let _ = writeln!(out, "{}{{route=\"{}\"}} {}", name, route, value(metrics).load(Ordering::Relaxed));
+        }
+    };
+    counter(&mut out, "porty_connections_total", "Client connections accepted", |m| &m.connections);
+    counter(&mut out, "porty_bytes_received_total", "Bytes read from clients", |m| &m.bytes_received);
+    counter(&mut out, "porty_bytes_sent_total", "Bytes written to clients", |m| &m.bytes_sent);
+    counter(&mut out, "porty_http_retries_total", "HTTP requests retried against a backend", |m| &m.retries);
+    counter(&mut out, "porty_backend_connect_failures_total", "Failed backend connection attempts", |m| &m.connect_failures);
+    counter(&mut out, "porty_timeouts_total", "Handshakes and requests that timed out", |m| &m.timeouts);
+    counter(&mut out, "porty_rejected_connections_total", "Clients refused by allow/deny lists or the UDP session limit", |m| &m.rejected_connections);
+
+    let _ = writeln!(out, "# HELP porty_active_connections Client connections currently open");
+    let _ = writeln!(out, "# TYPE porty_active_connections gauge");
+    for (route, metrics) in &routes {
+        let _ = writeln!(
+            out,
+            "porty_active_connections{{route=\"{}\"}} {}",
+            route,
+            metrics.active_connections.load(Ordering::Relaxed)
+        );
+    }
+
+    let _ = writeln!(out, "# HELP porty_http_requests_total HTTP responses by status class");
+    let _ = writeln!(out, "# TYPE porty_http_requests_total counter");
+    for (route, metrics) in &routes {
+        for (class, counter) in STATUS_CLASSES.iter().zip(&metrics.http_responses) {
+            let _ = writeln!(
+                out,
+                "porty_http_requests_total{{route=\"{}\",class=\"{}\"}} {}",
+                route,
+                class,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    let histogram = |out: &mut String, name: &str, help: &str, value: fn(&RouteMetrics) -> &Histogram| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+        for (route, metrics) in &routes {
+            render_histogram(out, name, route, value(metrics));
+        }
+    };
+    histogram(
+        &mut out,
+        "porty_http_request_duration_seconds",
+        "Time from forwarding an HTTP request to the end of its response",
+        |m| &m.request_duration,
+    );
+    histogram(
+        &mut out,
+        "porty_backend_connect_duration_seconds",
+        "Time to open a backend connection",
+        |m| &m.connect_duration,
+    );
+
+    out
+}
+
+fn render_histogram(out: &mut String, name: &str, route: &str, histogram: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{route=\"{}\",le=\"{}\"}} {}", name, route, bound, cumulative);
+    }
+    let count = histogram.count.load(Ordering::Relaxed);
+    let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "{}_bucket{{route=\"{}\",le=\"+Inf\"}} {}", name, route, count);
+    let _ = writeln!(out, "{}_sum{{route=\"{}\"}} {}", name, route, sum);
+    let _ = writeln!(out, "{}_count{{route=\"{}\"}} {}", name, route, count);
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serve `render()` to `GET /metrics` on `metrics_listen`.
+pub async fn serve_metrics(spec: &str) -> Result<()> {
+    let endpoint = Endpoint::parse(spec)?;
+    let listener = Listener::bind(&endpoint).await?;
+    log::info!("[metrics] Serving Prometheus metrics on {}", endpoint);
+
+    loop {
+        let (mut client, _, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            // Only the request line matters; clients that never send one are dropped
+            let request_line = match tokio::time::timeout(READ_TIMEOUT, read_request_line(&mut client)).await {
+                Ok(Ok(line)) => line,
+                _ => return,
+            };
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default();
+            let path = parts.next().unwrap_or_default().split('?').next().unwrap_or_default();
+
+            let (status, content_type, body) = if method == "GET" && path == "/metrics" {
+                ("200 OK", "text/plain; version=0.0.4", render())
+            } else {
+                ("404 Not Found", "text/plain", "404 Not Found\n".to_string())
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                body.len(),
+                body
+            );
+            let _ = client.write_all(response.as_bytes()).await;
+            let _ = client.shutdown().await;
+        });
+    }
+}
+
+// Read up to the end of the request line, at most 1 KiB
+async fn read_request_line<S: AsyncRead + Unpin>(client: &mut S) -> Result<String> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !data.contains(&b'\n') {
+        if data.len() >= buffer.len() {
+            return Err(anyhow::anyhow!("Request line too long"));
+        }
+        let n = client.read(&mut buffer).await?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("Connection closed before request line"));
+        }
+        data.extend_from_slice(&buffer[..n]);
+    }
+    let end = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+    Ok(String::from_utf8_lossy(&data[..end]).trim_end().to_string())
+}
@@ -3,142 +3,789 @@
 use anyhow::{Context, Result};
 use chrono::Local;
 use log::error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Semaphore;
-use crate::cfg::Config;
+use tokio::sync::{watch, Mutex, Semaphore};
+use tokio_rustls::TlsAcceptor;
+use crate::access::{self, classify, error_kind, AccessRecord, ErrorKind, LogFormat};
+use crate::acl;
+use crate::admin::{serve_admin, AdminSettings};
+use crate::balance::{client_ip, BalanceContext};
+use crate::cfg::{Config, Route};
+use crate::health::spawn_health_checks;
 use crate::http::handle_http_connection;
+use crate::logfile::watch_log_files;
+use crate::metrics::{self, serve_metrics, RouteMetrics};
+use crate::proxy_protocol::{self, read_header as read_proxy_header, ProxyAddresses};
+use crate::tls::{build_acceptor, read_client_hello, SniRouter};
+use crate::reload::{watch_config, Supervisor};
+use crate::shutdown::{shutdown_signal, ConnectionInfo, ConnectionTracker, DrainTimeout};
+use crate::vhost::VirtualHosts;
 use rsb::prelude::*;
 
+/// Any bidirectional byte stream a route can accept from or forward to.
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+pub type BoxedStream = Box<dyn AsyncStream>;
+
+/// Counts the bytes read from and written to a client stream on its connection.
+struct CountingStream {
+    inner: BoxedStream,
+    connection: Arc<ConnectionInfo>,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.connection.bytes_in.fetch_add((buf.filled().len() - before) as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, data);
+        if let Poll::Ready(Ok(n)) = &result {
+            self.connection.bytes_out.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A listen or target address: `host:port` or `unix:/path/to.sock`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    pub fn parse(spec: &str) -> Result<Endpoint> {
+        if let Some(path) = spec.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(anyhow::anyhow!("Invalid endpoint '{}': empty socket path", spec));
+            }
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+        }
+
+        let (host, port) = spec
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid endpoint '{}': expected host:port or unix:/path", spec))?;
+        let port = port
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid endpoint '{}': bad port '{}'", spec, port))?;
+
+        Ok(Endpoint::tcp(host, port))
+    }
+
+    pub fn tcp(host: &str, port: u16) -> Endpoint {
+        // Accept bracketed IPv6 literals like [::1]
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        Endpoint::Tcp { host: host.to_string(), port }
+    }
+
+    pub fn is_unix(&self) -> bool {
+        matches!(self, Endpoint::Unix(_))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp { host, port } if host.contains(':') => write!(f, "[{}]:{}", host, port),
+            Endpoint::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    pub async fn bind(endpoint: &Endpoint) -> Result<Listener> {
+        match endpoint {
+            Endpoint::Tcp { host, port } => {
+                let listener = TcpListener::bind((host.as_str(), *port))
+                    .await
+                    .context(format!("Failed to bind to {}", endpoint))?;
+                Ok(Listener::Tcp(listener))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                // Clear a stale socket left behind by a previous run
+                if let Ok(meta) = std::fs::symlink_metadata(path) {
+                    use std::os::unix::fs::FileTypeExt;
+                    if meta.file_type().is_socket() {
+                        std::fs::remove_file(path)
+                            .context(format!("Failed to remove stale socket {}", path.display()))?;
+                    }
+                }
+                let listener = tokio::net::UnixListener::bind(path)
+                    .context(format!("Failed to bind to {}", endpoint))?;
+                Ok(Listener::Unix(listener))
+            }
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) => Err(anyhow::anyhow!("Unix sockets are not supported on this platform: {}", endpoint)),
+        }
+    }
+
+    /// Accept a client: its stream, its address and the local address it connected to.
+    pub async fn accept(&self) -> Result<(BoxedStream, String, Option<SocketAddr>)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                let local = stream.local_addr().ok();
+                Ok((Box::new(stream), addr.to_string(), local))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                let peer = match addr.as_pathname() {
+                    Some(path) => format!("unix:{}", path.display()),
+                    None => "unix:(unnamed)".to_string(),
+                };
+                Ok((Box::new(stream), peer, None))
+            }
+        }
+    }
+}
+
+pub async fn connect_endpoint(endpoint: &Endpoint) -> Result<BoxedStream> {
+    match endpoint {
+        Endpoint::Tcp { host, port } => {
+            let stream = TcpStream::connect((host.as_str(), *port))
+                .await
+                .context(format!("Failed to connect to target {}", endpoint))?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(path)
+                .await
+                .context(format!("Failed to connect to target {}", endpoint))?;
+            Ok(Box::new(stream))
+        }
+        #[cfg(not(unix))]
+        Endpoint::Unix(_) => Err(anyhow::anyhow!("Unix sockets are not supported on this platform: {}", endpoint)),
+    }
+}
+
 pub async fn run_route(
-    name: &str,
+    route: Route,
     listen_addr: &str,
-    listen_port: u16,
-    target_addr: &str,
-    target_port: u16,
     max_connections: usize,
     buffer_size_kb: usize,
     log_requests: bool,
     verbose: bool,
-    mode: &str,  // "tcp" or "http"
-    host: Option<String>,  // Host header matching for HTTP routes
-    log_level: &str, // "none", "basic", "verbose"
-    timeout_seconds: u64, // Connection timeout
-    max_retries: u32, // Max retry attempts
 ) -> Result<()> {
-    let semaphore = Arc::new(Semaphore::new(max_connections));
-    let listen_addr_full = format!("{}:{}", listen_addr, listen_port);
-    let target_addr_full = format!("{}:{}", target_addr, target_port);
+    run_listener(vec![route], listen_addr, max_connections, buffer_size_kb, log_requests, verbose).await
+}
+
+/// Serve one listen address. Several HTTP routes may share it as virtual hosts;
+/// every other mode gets a listener of its own.
+pub async fn run_listener(
+    routes: Vec<Route>,
+    listen_addr: &str,
+    max_connections: usize,
+    buffer_size_kb: usize,
+    log_requests: bool,
+    verbose: bool,
+) -> Result<()> {
+    let state = Arc::new(ListenerState::build(
+        routes,
+        listen_addr,
+        buffer_size_kb,
+        log_requests,
+        LogFormat::Default,
+        verbose,
+    )?);
+    let listener = Listener::bind(&state.listen_endpoint).await?;
+    state.start_health_checks()?;
+
+    // Nothing ever replaces the state, so keep the sender alive for the whole run
+    let (_state_tx, state_rx) = watch::channel(state);
+    serve_listener(listener, state_rx, max_connections, ConnectionTracker::new()).await
+}
+
+/// Everything a stream listener needs to handle a connection. Reloads swap in a
+/// new state for new connections; connections already running keep their own.
+pub struct ListenerState {
+    pub listen_endpoint: Endpoint,
+    pub listener_name: String,
+    vhosts: Arc<VirtualHosts>,
+    route: Arc<Route>,
+    target_label: String,
+    tls_acceptor: Option<TlsAcceptor>,
+    sni_router: Option<Arc<SniRouter>>,
+    metrics: Arc<RouteMetrics>,
+    buffer_size: usize,
+    log_requests: bool,
+    log_format: LogFormat,
+    verbose: bool,
+}
+
+impl ListenerState {
+    pub fn build(
+        routes: Vec<Route>,
+        listen_addr: &str,
+        buffer_size_kb: usize,
+        log_requests: bool,
+        log_format: LogFormat,
+        verbose: bool,
+    ) -> Result<ListenerState> {
+        let primary = routes.first().ok_or_else(|| anyhow::anyhow!("Listener has no routes"))?;
+        let listen_endpoint = primary.listen_endpoint(listen_addr)?;
+        let names: Vec<&str> = routes.iter().map(|r| r.name.as_str()).collect();
+        let listener_name = names.join("+");
+
+        if routes.len() > 1 && routes.iter().any(|r| r.mode != "http") {
+            return Err(anyhow::anyhow!(
+                "Routes {} share {} but only http routes can share a listen address",
+                names.join(", "),
+                listen_endpoint
+            ));
+        }
+
+        let vhosts = Arc::new(VirtualHosts::new(routes.into_iter().map(Arc::new).collect())?);
+        for route in vhosts.routes() {
+            for rule in &route.paths {
+                Endpoint::parse(&rule.target)
+                    .context(format!("Invalid path target for '{}' on route '{}'", rule.prefix, route.name))?;
+            }
+        }
+        let route = vhosts.default_route().unwrap_or(&vhosts.routes()[0]).clone();
+        let pool = vhosts.default_pool().clone();
+        let target_label = if vhosts.routes().len() > 1 {
+            format!("{} virtual hosts", vhosts.routes().len())
+        } else {
+            pool.label()
+        };
+
+        if route.accept_proxy_protocol && route.proxy_protocol_from.is_empty() && !listen_endpoint.is_unix() {
+            return Err(anyhow::anyhow!(
+                "Route '{}' sets accept_proxy_protocol without proxy_protocol_from; list the load balancers that send PROXY headers",
+                route.name
+            ));
+        }
+        // The PROXY header is read before a virtual host is known, so every route on
+        // the listener must agree on how it is accepted
+        for other in vhosts.routes() {
+            if other.accept_proxy_protocol != route.accept_proxy_protocol
+                || other.proxy_protocol_from != route.proxy_protocol_from
+            {
+                return Err(anyhow::anyhow!(
+                    "Routes '{}' and '{}' share {} but set accept_proxy_protocol or proxy_protocol_from differently",
+                    route.name, other.name, listen_endpoint
+                ));
+            }
+            if other.send_proxy_protocol.is_some() && other.mode == "http" {
+                return Err(anyhow::anyhow!(
+                    "Route '{}' cannot send_proxy_protocol in http mode; use [routes.forwarded] headers instead",
+                    other.name
+                ));
+            }
+        }
 
-    let listener = TcpListener::bind(&listen_addr_full)
-        .await
-        .context(format!("Failed to bind to {}", listen_addr_full))?;
+        let tls_acceptor = build_acceptor(vhosts.routes(), vhosts.default_route().map(|r| r.as_ref()))?;
+        let sni_router = if route.mode == "tls-passthrough" {
+            if tls_acceptor.is_some() {
+                return Err(anyhow::anyhow!("Route '{}' cannot terminate TLS in tls-passthrough mode", route.name));
+            }
+            Some(Arc::new(SniRouter::from_route(&route, pool)?))
+        } else {
+            None
+        };
 
-    log::info!("[{}] Listening on {} -> {}", name, listen_addr_full, target_addr_full);
+        Ok(ListenerState {
+            metrics: metrics::route(&listener_name),
+            listen_endpoint,
+            listener_name,
+            vhosts,
+            route,
+            target_label,
+            tls_acceptor,
+            sni_router,
+            buffer_size: buffer_size_kb * 1024,
+            log_requests,
+            log_format,
+            verbose,
+        })
+    }
+
+    /// Checkers stop by themselves once this state is replaced and its last connection ends.
+    pub fn start_health_checks(&self) -> Result<()> {
+        for (route, pool) in self.vhosts.routes().iter().zip(self.vhosts.pools()) {
+            spawn_health_checks(route, pool)?;
+        }
+        Ok(())
+    }
+
+    /// Carry backend health over from the state this one replaces, for checked routes
+    /// that kept their name.
+    pub fn inherit_health(&self, previous: &ListenerState) {
+        for (route, pool) in self.vhosts.routes().iter().zip(self.vhosts.pools()) {
+            if route.health_check.is_none() {
+                continue;
+            }
+            let old = previous.vhosts.routes().iter().position(|old| old.name == route.name);
+            if let Some(index) = old {
+                pool.inherit_health(&previous.vhosts.pools()[index]);
+            }
+        }
+    }
+}
+
+/// Accept connections until the state sender is dropped. Connections in flight
+/// when the listener stops run to completion.
+pub async fn serve_listener(
+    listener: Listener,
+    mut state_rx: watch::Receiver<Arc<ListenerState>>,
+    max_connections: usize,
+    connections: Arc<ConnectionTracker>,
+) -> Result<()> {
+    let semaphore = Arc::new(Semaphore::new(max_connections));
+    let mut state = state_rx.borrow_and_update().clone();
+
+    log::info!(
+        "[{}] Listening on {}{} -> {}",
+        state.listener_name,
+        state.listen_endpoint,
+        if state.tls_acceptor.is_some() { " (tls)" } else { "" },
+        state.target_label
+    );
 
     loop {
-        let (client, client_addr) = listener.accept().await?;
-        let target_addr = target_addr_full.clone();
-        let permit = semaphore.clone().acquire_owned().await?;
-        let buffer_size = buffer_size_kb * 1024;
-        let route_name = name.to_string();
-        let route_mode = mode.to_string();
-        let route_host = host.clone();
-        let route_log_level = log_level.to_string();
-        let route_timeout = timeout_seconds;
-        let route_retries = max_retries;
+        let (client, client_addr, local_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            changed = state_rx.changed() => {
+                if changed.is_err() {
+                    log::info!("[{}] Stopped listening on {}", state.listener_name, state.listen_endpoint);
+                    return Ok(());
+                }
+                state = state_rx.borrow_and_update().clone();
+                continue;
+            }
+        };
+
+        // Only the listed load balancers may vouch for another client's address
+        if state.route.accept_proxy_protocol && !acl::trusts_proxy_header(&state.route, &client_addr) {
+            let e = anyhow::anyhow!("peer is not in proxy_protocol_from");
+            reject_proxy_header(&state, &client_addr, ErrorKind::Forbidden, &e);
+            continue;
+        }
+        // Refused clients never take a connection slot. HTTP routes answer them with
+        // a 403 once the virtual host is known, so they are served without one;
+        // addresses from a PROXY header are checked after it is read.
+        let admitted = state.route.accept_proxy_protocol
+            || state.vhosts.routes().iter().any(|route| acl::admits(route, &client_addr));
+        let permit = if admitted {
+            Some(semaphore.clone().acquire_owned().await?)
+        } else if state.route.mode == "http" {
+            None
+        } else {
+            reject_client(&state, &client_addr);
+            continue;
+        };
+        let connections = connections.clone();
+        let state = state.clone();
 
         tokio::spawn(async move {
+            let mut client = client;
+            let mut client_addr = client_addr;
+            let mut addresses = ProxyAddresses { source: client_addr.parse().ok(), destination: local_addr };
+
+            // Behind another load balancer the client address comes from its PROXY header
+            if state.route.accept_proxy_protocol {
+                let header_timeout = std::time::Duration::from_secs(state.route.timeout_seconds);
+                let header = match tokio::time::timeout(header_timeout, read_proxy_header(&mut client)).await {
+                    Ok(header) => header.map_err(|e| (ErrorKind::BadRequest, e)),
+                    Err(_) => Err((ErrorKind::Timeout, anyhow::anyhow!("Timed out waiting for PROXY header"))),
+                };
+                match header {
+                    Ok(Some(header)) => {
+                        addresses = header;
+                        if let Some(source) = header.source {
+                            client_addr = source.to_string();
+                        }
+                    }
+                    Ok(None) => {}
+                    Err((kind, e)) => {
+                        reject_proxy_header(&state, &client_addr, kind, &e);
+                        return;
+                    }
+                }
+                if state.route.mode != "http" && !acl::admits(&state.route, &client_addr) {
+                    reject_client(&state, &client_addr);
+                    return;
+                }
+            }
+            let connection = connections.track(&state.listener_name, &client_addr);
+            let active = state.metrics.connection_opened();
+
             let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+            let listener_name = &state.listener_name;
+            let route = &state.route;
+            let info = connection.info().clone();
+            // Structured formats log a record per connection (per request for HTTP)
+            // instead of text lines; an access_log file gets records in any format
+            let structured = state.log_requests && state.log_format.is_structured();
+            let records = state.log_requests && access::wants_records(state.log_format, listener_name);
+
+            // Terminate TLS first so the mode handlers see plaintext
+            let client: BoxedStream = match &state.tls_acceptor {
+                Some(acceptor) => {
+                    let handshake_timeout = std::time::Duration::from_secs(route.timeout_seconds);
+                    match tokio::time::timeout(handshake_timeout, acceptor.accept(client)).await {
+                        Ok(Ok(stream)) => Box::new(stream),
+                        Ok(Err(e)) => {
+                            if records {
+                                log_connection(&state, &info, Some(ErrorKind::Tls));
+                            }
+                            if !structured {
+                                stderr!(
+                                    "❌ [{}] {} | TLS handshake failed for {}: {}",
+                                    listener_name, timestamp, client_addr, e
+                                );
+                            }
+                            return;
+                        }
+                        Err(_) => {
+                            state.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+                            if records {
+                                log_connection(&state, &info, Some(ErrorKind::Timeout));
+                            }
+                            if !structured {
+                                stderr!(
+                                    "❌ [{}] {} | TLS handshake timed out for {}",
+                                    listener_name, timestamp, client_addr
+                                );
+                            }
+                            return;
+                        }
+                    }
+                }
+                None => client,
+            };
+            let client: BoxedStream = Box::new(CountingStream { inner: client, connection: info.clone() });
 
             // Print forward request message
-            if log_requests {
+            if state.log_requests && !structured {
                 echo!(
                     "🔄 [{}] {} | New connection from {} -> forwarding to {}",
-                    route_name, timestamp, client_addr, target_addr
+                    listener_name, timestamp, client_addr, state.target_label
                 );
             }
 
             let start_time = std::time::Instant::now();
-            let mut bytes_transferred = 0u64;
-
-            // Route based on mode: TCP or HTTP
-            let connection_result = if route_mode == "http" {
-                // Use HTTP handler for dynamic routing and host header matching
-                match handle_http_connection(client, route_name.clone(), target_addr.clone(), route_host, log_requests, verbose, &route_log_level, route_timeout, route_retries).await {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(e),
+
+            // Route based on mode: TCP, HTTP or TLS passthrough
+            let handler = async {
+                if route.mode == "http" {
+                    // Use HTTP handler for virtual hosts, dynamic routing and host header matching
+                    handle_http_connection(
+                        client,
+                        &info,
+                        &state.vhosts,
+                        state.tls_acceptor.is_some(),
+                        state.log_requests,
+                        state.log_format,
+                        state.verbose,
+                    ).await
+                } else if let Some(sni_router) = &state.sni_router {
+                    // Pick the backend from the ClientHello SNI, then splice raw bytes
+                    handle_tls_passthrough(client, &info, &state, sni_router, &addresses).await
+                } else {
+                    // Default TCP forwarding
+                    handle_tcp_connection(client, &info, &state, &addresses).await
                 }
-            } else {
-                // Default TCP forwarding
-                handle_tcp_connection(client, target_addr.clone(), buffer_size, &mut bytes_transferred).await
             };
+            // Dropping the handler closes both sides of a connection killed via the admin API
+            let connection_result = tokio::select! {
+                result = handler => result,
+                _ = info.killed() => Err(classify(ErrorKind::Killed, anyhow::anyhow!("Closed via admin API"))),
+            };
+
+            // HTTP requests were already logged one by one
+            if records {
+                let kind = connection_result.as_ref().err().map(error_kind);
+                if route.mode != "http" || kind == Some(ErrorKind::Killed) {
+                    log_connection(&state, &info, kind);
+                }
+            }
 
             match connection_result {
+                _ if structured => {}
                 Ok(_) => {
                     let duration = start_time.elapsed();
-                    if verbose {
+                    if state.verbose {
                         echo!(
                             "✅ [{}] {} | Connection closed: {} | Duration: {:.2}s | Transferred: {} bytes",
-                            route_name,
+                            listener_name,
                             timestamp,
                             client_addr,
                             duration.as_secs_f64(),
-                            format_bytes(bytes_transferred)
+                            format_bytes(info.bytes())
                         );
                     }
                 }
                 Err(e) => {
                     stderr!(
                         "❌ [{}] {} | Connection error for {}: {}",
-                        route_name, timestamp, client_addr, e
+                        listener_name, timestamp, client_addr, e
                     );
                 }
             }
 
+            drop(active);
             drop(permit);
+            drop(connection);
         });
     }
 }
 
+/// Count and log a client refused by the access lists. Dropping its stream closes it.
+fn reject_client(state: &ListenerState, client_addr: &str) {
+    state.metrics.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    if state.log_requests && access::wants_records(state.log_format, &state.listener_name) {
+        let mut record = AccessRecord::new(&state.listener_name, client_addr);
+        record.error = Some(ErrorKind::Forbidden);
+        record.emit(state.log_format, std::time::Duration::ZERO);
+    }
+    if !(state.log_requests && state.log_format.is_structured()) {
+        stderr!(
+            "🚫 [{}] {} | Rejected {} by access list",
+            state.listener_name,
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            client_addr
+        );
+    }
+}
+
+/// Count and log a client whose PROXY header was missing or malformed.
+fn reject_proxy_header(state: &ListenerState, client_addr: &str, kind: ErrorKind, e: &anyhow::Error) {
+    state.metrics.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    if kind == ErrorKind::Timeout {
+        state.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+    if state.log_requests && access::wants_records(state.log_format, &state.listener_name) {
+        let mut record = AccessRecord::new(&state.listener_name, client_addr);
+        record.error = Some(kind);
+        record.emit(state.log_format, std::time::Duration::ZERO);
+    }
+    if !(state.log_requests && state.log_format.is_structured()) {
+        stderr!(
+            "❌ [{}] {} | PROXY header from {} rejected: {}",
+            state.listener_name,
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            client_addr,
+            e
+        );
+    }
+}
+
+/// The structured record for a whole connection.
+fn log_connection(state: &ListenerState, info: &ConnectionInfo, error: Option<ErrorKind>) {
+    let mut record = AccessRecord::new(&state.listener_name, &info.client);
+    record.target = info.target();
+    record.bytes_in = info.bytes_in.load(Ordering::Relaxed);
+    record.bytes_out = info.bytes_out.load(Ordering::Relaxed);
+    record.error = error;
+    record.emit(state.log_format, info.started.elapsed());
+}
+
+/// Connect to a backend, recording connect latency and failures.
+pub async fn connect_backend(endpoint: &Endpoint, metrics: &RouteMetrics) -> Result<BoxedStream> {
+    let start = std::time::Instant::now();
+    match connect_endpoint(endpoint).await {
+        Ok(stream) => {
+            metrics.connect_duration.observe(start.elapsed());
+            Ok(stream)
+        }
+        Err(e) => {
+            metrics.connect_failures.fetch_add(1, Ordering::Relaxed);
+            Err(classify(ErrorKind::Connect, e))
+        }
+    }
+}
+
 async fn handle_tcp_connection(
-    client: TcpStream,
-    target_addr: String,
-    buffer_size: usize,
-    bytes_transferred: &mut u64,
+    client: BoxedStream,
+    connection: &ConnectionInfo,
+    state: &ListenerState,
+    addresses: &ProxyAddresses,
 ) -> Result<()> {
-    let target = TcpStream::connect(&target_addr)
-        .await
-        .context(format!("Failed to connect to target {}", target_addr))?;
+    let lease = state.vhosts.default_pool()
+        .select(&BalanceContext { client_ip: client_ip(&connection.client), header_value: None })
+        .ok_or_else(|| classify(ErrorKind::NoBackend, anyhow::anyhow!("No healthy backend available")))?;
+    connection.set_target(lease.endpoint());
+    let mut target = connect_backend(lease.endpoint(), &state.metrics).await?;
+    send_proxy_header(&mut target, state, addresses).await?;
+    splice(client, target, state.buffer_size, &state.metrics, None).await
+}
 
-    let (client_read, client_write) = client.into_split();
-    let (target_read, target_write) = target.into_split();
+async fn handle_tls_passthrough(
+    mut client: BoxedStream,
+    connection: &ConnectionInfo,
+    state: &ListenerState,
+    sni_router: &SniRouter,
+    addresses: &ProxyAddresses,
+) -> Result<()> {
+    let route = &state.route;
+    let hello_timeout = std::time::Duration::from_secs(route.timeout_seconds);
+    let (client_hello, server_name) = match tokio::time::timeout(hello_timeout, read_client_hello(&mut client)).await {
+        Ok(hello) => hello.map_err(|e| classify(ErrorKind::Tls, e))?,
+        Err(_) => {
+            state.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+            return Err(classify(ErrorKind::Timeout, anyhow::anyhow!("Timed out waiting for TLS ClientHello")));
+        }
+    };
+    state.metrics.bytes_received.fetch_add(client_hello.len() as u64, Ordering::Relaxed);
 
-    let client_to_target = forward_data(client_read, target_write, buffer_size);
-    let target_to_client = forward_data(target_read, client_write, buffer_size);
+    // SNI targets first, then the route's own backends
+    let lease;
+    let target_endpoint = match sni_router.select(server_name.as_deref()) {
+        Some(endpoint) => endpoint,
+        None => {
+            lease = sni_router.pool()
+                .select(&BalanceContext { client_ip: client_ip(&connection.client), header_value: None })
+                .ok_or_else(|| classify(ErrorKind::NoBackend, anyhow::anyhow!("No healthy backend available")))?;
+            lease.endpoint()
+        }
+    };
+    connection.set_target(target_endpoint);
+    if state.log_requests && route.log_level != "none" && !state.log_format.is_structured() {
+        echo!(
+            "   ├─ SNI: {} -> {}",
+            server_name.as_deref().unwrap_or("(none)"),
+            target_endpoint
+        );
+    }
 
-    tokio::select! {
-        result = client_to_target => {
-            if let Ok(bytes) = result {
-                *bytes_transferred += bytes;
-            }
+    let mut target = connect_backend(target_endpoint, &state.metrics).await?;
+    send_proxy_header(&mut target, state, addresses).await?;
+    target.write_all(&client_hello).await?;
+
+    splice(client, target, state.buffer_size, &state.metrics, None).await
+}
+
+/// Announce the client to the backend when the route sets `send_proxy_protocol`.
+async fn send_proxy_header(target: &mut BoxedStream, state: &ListenerState, addresses: &ProxyAddresses) -> Result<()> {
+    if let Some(version) = state.route.send_proxy_protocol {
+        let header = proxy_protocol::encode(version, addresses);
+        target.write_all(&header).await?;
+        state.metrics.bytes_received.fetch_add(header.len() as u64, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Copy bytes both ways until either side closes, or until neither side has sent
+/// anything for `idle`.
+pub(crate) async fn splice<C, T>(
+    client: C,
+    target: T,
+    buffer_size: usize,
+    metrics: &RouteMetrics,
+    idle: Option<Duration>,
+) -> Result<()>
+where
+    C: AsyncRead + AsyncWrite,
+    T: AsyncRead + AsyncWrite,
+{
+    let (client_read, client_write) = tokio::io::split(client);
+    let (target_read, target_write) = tokio::io::split(target);
+    let activity = Activity::new();
+
+    // Both directions count as they go; the one still running is cut off by select
+    let client_to_target = forward_data(client_read, target_write, buffer_size, &metrics.bytes_received, &activity);
+    let target_to_client = forward_data(target_read, client_write, buffer_size, &metrics.bytes_sent, &activity);
+    let idle_expired = async {
+        match idle {
+            Some(idle) => activity.idle_for(idle).await,
+            None => std::future::pending().await,
         }
-        result = target_to_client => {
-            if let Ok(bytes) = result {
-                *bytes_transferred += bytes;
-            }
+    };
+
+    tokio::select! {
+        _ = client_to_target => {}
+        _ = target_to_client => {}
+        _ = idle_expired => {
+            metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+            return Err(classify(ErrorKind::Timeout, anyhow::anyhow!("Idle for {} seconds", idle.unwrap_or_default().as_secs())));
         }
     }
 
     Ok(())
 }
 
-async fn forward_data(
-    mut reader: tokio::net::tcp::OwnedReadHalf,
-    mut writer: tokio::net::tcp::OwnedWriteHalf,
+/// When either direction of a splice last moved data.
+struct Activity {
+    start: Instant,
+    last_ms: AtomicU64, // Milliseconds after start
+}
+
+impl Activity {
+    fn new() -> Activity {
+        Activity { start: Instant::now(), last_ms: AtomicU64::new(0) }
+    }
+
+    fn touch(&self) {
+        self.last_ms.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    // Resolves once no data moved for `idle`
+    async fn idle_for(&self, idle: Duration) {
+        loop {
+            let deadline = self.start + Duration::from_millis(self.last_ms.load(Ordering::Relaxed)) + idle;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+}
+
+async fn forward_data<R, W>(
+    mut reader: R,
+    mut writer: W,
     buffer_size: usize,
-) -> Result<u64> {
+    metric: &AtomicU64,
+    activity: &Activity,
+) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
     let mut buffer = vec![0u8; buffer_size];
     let mut total_bytes = 0u64;
 
@@ -150,7 +797,9 @@ async fn forward_data(
 
         writer.write_all(&buffer[..n]).await?;
         writer.flush().await?;
+        activity.touch();
         total_bytes += n as u64;
+        metric.fetch_add(n as u64, Ordering::Relaxed);
     }
 
     Ok(total_bytes)
@@ -166,64 +815,71 @@ pub fn format_bytes(bytes: u64) -> String {
     }
 }
 
-pub async fn run_porty_server(config: Config) -> Result<()> {
-    // Start additional routes if configured
-    let enabled_routes: Vec<_> = config.routes.iter()
-        .filter(|r| r.enabled)
-        .cloned()
-        .collect();
+pub async fn run_porty_server(config: Config, config_path: PathBuf) -> Result<()> {
+    let verbose = is_true("opt_verbose");
 
-    for route in enabled_routes {
-        let listen_addr = config.listen_addr.clone();
-        let max_conn = config.max_connections;
-        let buffer_size = config.buffer_size_kb;
-        let log_requests = config.log_requests;
-        let verbose = is_true("opt_verbose");
+    // The main route must come up; broken additional routes are logged and skipped
+    let mut supervisor = Supervisor::new(verbose);
+    supervisor.apply(&config, true).await?;
+    let connections = supervisor.connections().clone();
+    let supervisor = Arc::new(Mutex::new(supervisor));
 
-        echo!("🔊 Additional route '{}': {}:{} -> {}:{}",
-            route.name, listen_addr, route.listen_port,
-            route.target_addr, route.target_port);
+    // Log files are reopened on SIGUSR1 and rotated on schedule
+    tokio::spawn(async {
+        if let Err(e) = watch_log_files().await {
+            error!("Log file watcher failed: {}", e);
+        }
+    });
 
-        let route_mode = route.mode.clone();
+    // Not reloaded: the metrics and admin listeners live for the whole process
+    if let Some(spec) = config.metrics_listen.clone() {
         tokio::spawn(async move {
-            if let Err(e) = run_route(
-                &route.name,
-                &listen_addr,
-                route.listen_port,
-                &route.target_addr,
-                route.target_port,
-                max_conn,
-                buffer_size,
-                log_requests,
-                verbose,
-                &route_mode,
-                route.host.clone(),
-                &route.log_level,
-                route.timeout_seconds,
-                route.max_retries,
-            ).await {
-                error!("Route {} failed: {}", route.name, e);
+            if let Err(e) = serve_metrics(&spec).await {
+                error!("Metrics listener {} failed: {}", spec, e);
             }
         });
     }
+    if let Some(spec) = config.admin_listen.clone() {
+        let supervisor = supervisor.clone();
+        let settings = AdminSettings::from_config(&config);
+        tokio::spawn(async move {
+            if let Err(e) = serve_admin(&spec, settings, supervisor).await {
+                error!("Admin listener {} failed: {}", spec, e);
+            }
+        });
+    }
+
+    let signal = tokio::select! {
+        result = watch_config(config_path, supervisor.clone()) => return result,
+        signal = shutdown_signal() => signal?,
+    };
 
-    // Run main route (default to TCP mode)
-    run_route(
-        "main",
-        &config.listen_addr,
-        config.listen_port,
-        &config.target_addr,
-        config.target_port,
-        config.max_connections,
-        config.buffer_size_kb,
-        config.log_requests,
-        is_true("opt_verbose"),
-        "tcp",  // Main route defaults to TCP
-        None,   // Main route has no host matching
-        "basic", // Main route uses basic logging
-        30,     // 30 second timeout
-        2,      // 2 retries
-    ).await?;
+    // Stop accepting everywhere, then let open connections finish
+    let drain_timeout = {
+        let mut supervisor = supervisor.lock().await;
+        supervisor.stop_all();
+        connections.start_draining();
+        supervisor.drain_timeout()
+    };
+    access::notice(&format!(
+        "🛑 {} received, no longer accepting connections. Draining {} open connection(s) (up to {}s)...",
+        signal,
+        connections.active(),
+        drain_timeout.as_secs()
+    ));
+
+    tokio::select! {
+        drained = connections.drain(drain_timeout) => {
+            if !drained {
+                return Err(DrainTimeout { remaining: connections.active() }.into());
+            }
+        }
+        signal = shutdown_signal() => {
+            stderr!("⚠️  {} received again, exiting without waiting for connections", signal?);
+            return Err(DrainTimeout { remaining: connections.active() }.into());
+        }
+    }
 
+    access::notice("✅ All connections drained, exiting");
     Ok(())
-}
\ No newline at end of file
+}
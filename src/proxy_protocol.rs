@@ -0,0 +1,178 @@
+// PROXY protocol v1 and v2 headers: the real client address across a proxy hop
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LENGTH: usize = 107; // Longest v1 line, CRLF included
+
+/// The `send_proxy_protocol` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyVersion {
+    V1, // Text line
+    V2, // Binary header
+}
+
+/// Source and destination of a connection. `None` when a side is not an IP
+/// socket, such as a Unix socket peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProxyAddresses {
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+}
+
+impl ProxyAddresses {
+    // Both ends as one address family; v1 and v2 cannot mix IPv4 and IPv6
+    fn pair(&self) -> Option<(SocketAddr, SocketAddr)> {
+        let (source, destination) = (self.source?, self.destination?);
+        if source.is_ipv4() == destination.is_ipv4() {
+            return Some((source, destination));
+        }
+        let v6 = |addr: SocketAddr| match addr.ip() {
+            IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+            IpAddr::V6(_) => addr,
+        };
+        Some((v6(source), v6(destination)))
+    }
+}
+
+/// The header announcing `addresses` to a backend. Connections without IP
+/// addresses on both ends are sent as `UNKNOWN` (v1) or `LOCAL` (v2).
+pub fn encode(version: ProxyVersion, addresses: &ProxyAddresses) -> Vec<u8> {
+    let pair = addresses.pair();
+    match version {
+        ProxyVersion::V1 => match pair {
+            Some((source, destination)) => format!(
+                "PROXY {} {} {} {} {}\r\n",
+                if source.is_ipv4() { "TCP4" } else { "TCP6" },
+                source.ip(),
+                destination.ip(),
+                source.port(),
+                destination.port()
+            )
+            .into_bytes(),
+            None => b"PROXY UNKNOWN\r\n".to_vec(),
+        },
+        ProxyVersion::V2 => {
+            let mut header = V2_SIGNATURE.to_vec();
+            match pair {
+                Some((source, destination)) => {
+                    let mut body = Vec::with_capacity(36);
+                    let family = match (source.ip(), destination.ip()) {
+                        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                            body.extend_from_slice(&src.octets());
+                            body.extend_from_slice(&dst.octets());
+                            0x11 // TCP over IPv4
+                        }
+                        (src, dst) => {
+                            body.extend_from_slice(&ipv6_octets(src));
+                            body.extend_from_slice(&ipv6_octets(dst));
+                            0x21 // TCP over IPv6
+                        }
+                    };
+                    body.extend_from_slice(&source.port().to_be_bytes());
+                    body.extend_from_slice(&destination.port().to_be_bytes());
+                    header.extend_from_slice(&[0x21, family]); // Version 2, PROXY command
+                    header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+                    header.extend_from_slice(&body);
+                }
+                None => header.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]), // Version 2, LOCAL command
+            }
+            header
+        }
+    }
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+/// Read a v1 or v2 header from the start of `stream`, without reading past it.
+/// Returns the addresses it carries, or `None` for `UNKNOWN`/`LOCAL` headers
+/// and address families other than TCP/UDP over IP.
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<ProxyAddresses>> {
+    let mut start = [0u8; 6];
+    stream.read_exact(&mut start).await?;
+    if &start == b"PROXY " {
+        read_v1(stream).await
+    } else if start == V2_SIGNATURE[..6] {
+        read_v2(stream).await
+    } else {
+        Err(anyhow::anyhow!("Connection did not start with a PROXY protocol header"))
+    }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<ProxyAddresses>> {
+    // One byte at a time so nothing after the CRLF is consumed
+    let mut line = b"PROXY ".to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(anyhow::anyhow!("PROXY v1 header longer than {} bytes", V1_MAX_LENGTH));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = String::from_utf8_lossy(&line[..line.len() - 2]).to_string();
+    let invalid = || anyhow::anyhow!("Invalid PROXY v1 header '{}'", line);
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.get(1).copied() {
+        Some("UNKNOWN") => Ok(None),
+        Some(family @ ("TCP4" | "TCP6")) if fields.len() == 6 => {
+            // Both addresses must belong to the family the line names
+            let ipv4 = family == "TCP4";
+            let ip = |field: &str| field.parse::<IpAddr>().ok().filter(|ip| ip.is_ipv4() == ipv4).ok_or_else(invalid);
+            let port = |field: &str| field.parse::<u16>().map_err(|_| invalid());
+            Ok(Some(ProxyAddresses {
+                source: Some(SocketAddr::new(ip(fields[2])?, port(fields[4])?)),
+                destination: Some(SocketAddr::new(ip(fields[3])?, port(fields[5])?)),
+            }))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<ProxyAddresses>> {
+    let mut rest = [0u8; 10];
+    stream.read_exact(&mut rest).await?;
+    if rest[..6] != V2_SIGNATURE[6..] {
+        return Err(anyhow::anyhow!("Invalid PROXY v2 signature"));
+    }
+    let (version_command, family) = (rest[6], rest[7]);
+    if version_command >> 4 != 2 {
+        return Err(anyhow::anyhow!("Unsupported PROXY protocol version {}", version_command >> 4));
+    }
+    let length = u16::from_be_bytes([rest[8], rest[9]]) as usize;
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).await?;
+
+    if version_command & 0x0f == 0 {
+        return Ok(None); // LOCAL: health checks from the proxy itself
+    }
+    let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+    // TLVs after the addresses are skipped
+    match family >> 4 {
+        1 if length >= 12 => {
+            let source: [u8; 4] = body[0..4].try_into()?;
+            let destination: [u8; 4] = body[4..8].try_into()?;
+            Ok(Some(ProxyAddresses {
+                source: Some(SocketAddr::new(IpAddr::from(source), port(8))),
+                destination: Some(SocketAddr::new(IpAddr::from(destination), port(10))),
+            }))
+        }
+        2 if length >= 36 => {
+            let source: [u8; 16] = body[0..16].try_into()?;
+            let destination: [u8; 16] = body[16..32].try_into()?;
+            Ok(Some(ProxyAddresses {
+                source: Some(SocketAddr::new(IpAddr::from(source), port(32))),
+                destination: Some(SocketAddr::new(IpAddr::from(destination), port(34))),
+            }))
+        }
+        1 | 2 => Err(anyhow::anyhow!("PROXY v2 address block too short")),
+        _ => Ok(None),
+    }
+}
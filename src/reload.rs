@@ -0,0 +1,413 @@
+// Hot configuration reload: diff routes and swap listener state in place
+
+use anyhow::Result;
+use log::error;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::net::UdpSocket;
+use tokio::sync::{watch, Mutex};
+use crate::access::{self, AccessFiles, LogFormat};
+use crate::balance::BackendPool;
+use crate::cfg::{load_config, Config, Route};
+use crate::logfile::{self, LogFile};
+use crate::net::{serve_listener, Endpoint, Listener, ListenerState};
+use crate::shutdown::ConnectionTracker;
+use crate::udp::{serve_udp, UdpState};
+use rsb::prelude::*;
+
+// How often the config file's modification time is checked
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Stream and datagram sockets may share an address, so they are keyed apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ListenerKey {
+    Stream(Endpoint),
+    Datagram(Endpoint),
+}
+
+enum StateSender {
+    Stream(watch::Sender<Arc<ListenerState>>),
+    Datagram(watch::Sender<Arc<UdpState>>),
+}
+
+enum BuiltState {
+    Stream(Arc<ListenerState>),
+    Datagram(Arc<UdpState>),
+}
+
+enum BoundSocket {
+    Stream(Listener),
+    Datagram(UdpSocket),
+}
+
+struct RunningListener {
+    routes: Vec<Route>,
+    settings: (usize, bool, LogFormat), // buffer_size_kb, log_requests, log_format
+    sender: StateSender,
+}
+
+/// Runtime changes made through the admin API. They are layered over every
+/// config applied afterwards, reloads included, until porty restarts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteOverride {
+    pub enabled: Option<bool>,
+    pub target: Option<String>,
+}
+
+/// Owns every running listener. Dropping a listener's state sender stops its
+/// accept loop; connections already in flight finish on their own.
+pub struct Supervisor {
+    listeners: HashMap<ListenerKey, RunningListener>,
+    connections: Arc<ConnectionTracker>,
+    config: Option<Config>,
+    overrides: HashMap<String, RouteOverride>,
+    drain_timeout: Duration,
+    stopped: bool,
+    verbose: bool,
+}
+
+impl Supervisor {
+    pub fn new(verbose: bool) -> Supervisor {
+        Supervisor {
+            listeners: HashMap::new(),
+            connections: ConnectionTracker::new(),
+            config: None,
+            overrides: HashMap::new(),
+            drain_timeout: Duration::ZERO,
+            stopped: false,
+            verbose,
+        }
+    }
+
+    /// The most recently applied config, without admin overrides.
+    pub fn config(&self) -> Option<&Config> {
+        self.config.as_ref()
+    }
+
+    /// Every configured route, main route first, with admin overrides applied.
+    pub fn routes(&self) -> Vec<Route> {
+        match &self.config {
+            Some(config) => std::iter::once(config.main_route())
+                .chain(config.routes.iter().cloned())
+                .map(|route| self.overridden(route))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn is_overridden(&self, name: &str) -> bool {
+        self.overrides.contains_key(name)
+    }
+
+    /// Merge an admin change into the route's override and re-apply the current
+    /// config. A change that fails to apply is rolled back.
+    pub async fn update_route(&mut self, name: &str, change: RouteOverride) -> Result<()> {
+        let config = self.config.clone().ok_or_else(|| anyhow::anyhow!("No configuration applied yet"))?;
+        if !self.routes().iter().any(|r| r.name == name) {
+            return Err(anyhow::anyhow!("Unknown route '{}'", name));
+        }
+
+        let previous = self.overrides.get(name).cloned();
+        let entry = self.overrides.entry(name.to_string()).or_default();
+        if change.enabled.is_some() {
+            entry.enabled = change.enabled;
+        }
+        if change.target.is_some() {
+            entry.target = change.target;
+        }
+
+        if let Err(e) = self.apply(&config, false).await {
+            match previous {
+                Some(previous) => self.overrides.insert(name.to_string(), previous),
+                None => self.overrides.remove(name),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn overridden(&self, mut route: Route) -> Route {
+        if let Some(change) = self.overrides.get(&route.name) {
+            if let Some(enabled) = change.enabled {
+                route.enabled = enabled;
+            }
+            if let Some(target) = &change.target {
+                route.target = Some(target.clone());
+                route.targets.clear();
+            }
+        }
+        route
+    }
+
+    /// Open client connections and UDP sessions on every listener this supervisor started.
+    pub fn connections(&self) -> &Arc<ConnectionTracker> {
+        &self.connections
+    }
+
+    /// Drain timeout of the most recently applied config.
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
+    /// Stop accepting on every listener for good. Open connections keep running.
+    pub fn stop_all(&mut self) {
+        self.stopped = true;
+        self.listeners.clear();
+    }
+
+    /// Start listeners for a config. At startup a broken additional route is logged
+    /// and skipped; on reload any error rejects the whole config and nothing changes.
+    pub async fn apply(&mut self, config: &Config, startup: bool) -> Result<()> {
+        if self.stopped {
+            return Err(anyhow::anyhow!("Shutting down"));
+        }
+        let main_route = config.with_global_lists(self.overridden(config.main_route()));
+        let log_format = LogFormat::parse(&config.log_format)?;
+        let settings = (config.buffer_size_kb, config.log_requests, log_format);
+        config.log_rotation.validate()?;
+        let access_files = AccessFiles::open(config)?;
+        let error_log = config.error_log.as_deref().map(LogFile::open).transpose()?;
+
+        // Routes bound to the same address share one listener (HTTP virtual hosts)
+        let mut groups: Vec<(ListenerKey, Vec<Route>)> = Vec::new();
+        let routes = std::iter::once(main_route.clone())
+            .chain(config.routes.iter().map(|r| config.with_global_lists(self.overridden(r.clone()))))
+            .filter(|r| r.enabled);
+        for route in routes {
+            let listen = match route.listen_endpoint(&config.listen_addr) {
+                Ok(listen) => listen,
+                Err(e) => {
+                    tolerate(startup && route != main_route, &route.name, e)?;
+                    continue;
+                }
+            };
+            let key = if route.mode == "udp" {
+                ListenerKey::Datagram(listen)
+            } else {
+                ListenerKey::Stream(listen)
+            };
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push(route),
+                None => groups.push((key, vec![route])),
+            }
+        }
+
+        // Build every changed listener before touching any running one
+        let mut built = Vec::new();
+        let mut kept = Vec::new();
+        for (key, routes) in groups {
+            if let Some(running) = self.listeners.get(&key) {
+                if running.routes == routes && running.settings == settings {
+                    kept.push(key);
+                    continue;
+                }
+            }
+
+            let optional = startup && !routes.contains(&main_route);
+            let state = match &key {
+                ListenerKey::Stream(_) => ListenerState::build(
+                    routes.clone(),
+                    &config.listen_addr,
+                    config.buffer_size_kb,
+                    config.log_requests,
+                    log_format,
+                    self.verbose,
+                ).map(|state| BuiltState::Stream(Arc::new(state))),
+                ListenerKey::Datagram(endpoint) if routes.len() > 1 => Err(anyhow::anyhow!(
+                    "Routes {} share udp://{}",
+                    route_names(&routes),
+                    endpoint
+                )),
+                ListenerKey::Datagram(_) => {
+                    UdpState::build(routes[0].clone(), &config.listen_addr, config.log_requests, log_format, self.verbose)
+                        .map(|state| BuiltState::Datagram(Arc::new(state)))
+                }
+            };
+            match state {
+                Ok(state) => built.push((key, routes, state)),
+                Err(e) => tolerate(optional, &route_names(&routes), e)?,
+            }
+        }
+
+        // Bind new addresses up front too, so a busy port rejects the reload
+        let mut bound = HashMap::new();
+        let mut ready = Vec::new();
+        for (key, routes, state) in built {
+            if !self.listeners.contains_key(&key) {
+                let socket = match &state {
+                    BuiltState::Stream(state) => Listener::bind(&state.listen_endpoint).await.map(BoundSocket::Stream),
+                    BuiltState::Datagram(state) => state.bind().await.map(BoundSocket::Datagram),
+                };
+                match socket {
+                    Ok(socket) => {
+                        bound.insert(key.clone(), socket);
+                    }
+                    Err(e) => {
+                        tolerate(startup && !routes.contains(&main_route), &route_names(&routes), e)?;
+                        continue;
+                    }
+                }
+            }
+            ready.push((key, routes, state));
+        }
+
+        // Commit: switch log files, stop removed listeners, then update or start the rest
+        logfile::set_rotation(config.log_rotation.clone());
+        access_files.install();
+        access::set_stdout_format(log_format, config.log_requests);
+        if let Err(e) = logfile::set_error_log(error_log) {
+            error!("Error log not switched: {:#}", e);
+        }
+
+        let wanted: Vec<&ListenerKey> = kept.iter().chain(ready.iter().map(|(key, _, _)| key)).collect();
+        let removed: Vec<ListenerKey> = self.listeners.keys()
+            .filter(|key| !wanted.contains(key))
+            .cloned()
+            .collect();
+        for key in removed {
+            if let Some(running) = self.listeners.remove(&key) {
+                access::notice(&format!("🛑 Route '{}' removed", route_names(&running.routes)));
+            }
+        }
+
+        for (key, routes, state) in ready {
+            if let Some(running) = self.listeners.get(&key) {
+                match (&running.sender, &state) {
+                    (StateSender::Stream(sender), BuiltState::Stream(state)) => state.inherit_health(&sender.borrow()),
+                    // UDP routes have no health checks
+                    (StateSender::Datagram(_), BuiltState::Datagram(_)) => {}
+                    _ => unreachable!("listener keys separate stream and datagram state"),
+                }
+            }
+            let health = match &state {
+                BuiltState::Stream(state) => state.start_health_checks(),
+                BuiltState::Datagram(state) => state.start_health_checks(),
+            };
+            if let Err(e) = health {
+                error!("Route {} health checks failed: {}", route_names(&routes), e);
+            }
+
+            if let Some(running) = self.listeners.get_mut(&key) {
+                match (&running.sender, state) {
+                    (StateSender::Stream(sender), BuiltState::Stream(state)) => {
+                        sender.send_replace(state);
+                    }
+                    (StateSender::Datagram(sender), BuiltState::Datagram(state)) => {
+                        sender.send_replace(state);
+                    }
+                    _ => unreachable!("listener keys separate stream and datagram state"),
+                }
+                access::notice(&format!("♻️  Route '{}' updated", route_names(&routes)));
+                running.routes = routes;
+                running.settings = settings;
+                continue;
+            }
+
+            for route in routes.iter().filter(|r| !startup || **r != main_route) {
+                if let (Ok(listen), Ok(pool)) = (route.listen_endpoint(&config.listen_addr), BackendPool::from_route(route)) {
+                    access::notice(&format!("🔊 Additional route '{}': {} -> {}", route.name, listen, pool.label()));
+                }
+            }
+
+            let names = route_names(&routes);
+            let max_connections = config.max_connections;
+            let sender = match (bound.remove(&key), state) {
+                (Some(BoundSocket::Stream(listener)), BuiltState::Stream(state)) => {
+                    let (sender, receiver) = watch::channel(state);
+                    let connections = self.connections.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_listener(listener, receiver, max_connections, connections).await {
+                            error!("Route {} failed: {}", names, e);
+                        }
+                    });
+                    StateSender::Stream(sender)
+                }
+                (Some(BoundSocket::Datagram(socket)), BuiltState::Datagram(state)) => {
+                    let (sender, receiver) = watch::channel(state);
+                    let connections = self.connections.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_udp(socket, receiver, max_connections, connections).await {
+                            error!("Route {} failed: {}", names, e);
+                        }
+                    });
+                    StateSender::Datagram(sender)
+                }
+                _ => unreachable!("every new listener was bound above"),
+            };
+            self.listeners.insert(key, RunningListener { routes, settings, sender });
+        }
+
+        self.drain_timeout = Duration::from_secs(config.drain_timeout_seconds);
+        self.config = Some(config.clone());
+        Ok(())
+    }
+}
+
+fn route_names(routes: &[Route]) -> String {
+    routes.iter().map(|r| r.name.as_str()).collect::<Vec<_>>().join("+")
+}
+
+// Startup skips a broken optional route; everything else is fatal
+fn tolerate(optional: bool, name: &str, e: anyhow::Error) -> Result<()> {
+    if optional {
+        error!("Route {} failed: {}", name, e);
+        Ok(())
+    } else {
+        Err(e.context(format!("Route {} failed", name)))
+    }
+}
+
+/// Reload on SIGHUP or when the config file changes on disk. A config that fails
+/// to load or apply is rejected and the running setup stays in place.
+pub async fn watch_config(config_path: PathBuf, supervisor: Arc<Mutex<Supervisor>>) -> Result<()> {
+    let mut last_modified = modified_time(&config_path);
+    let mut poll = tokio::time::interval(WATCH_INTERVAL);
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    loop {
+        #[cfg(unix)]
+        let signalled = tokio::select! {
+            _ = hangup.recv() => true,
+            _ = poll.tick() => false,
+        };
+        #[cfg(not(unix))]
+        let signalled = {
+            poll.tick().await;
+            false
+        };
+
+        let modified = modified_time(&config_path);
+        if signalled {
+            access::notice(&format!("🔁 SIGHUP received, reloading {}", config_path.display()));
+        } else if modified != last_modified {
+            access::notice(&format!("🔁 Config file changed, reloading {}", config_path.display()));
+        } else {
+            continue;
+        }
+        last_modified = modified;
+
+        match reload(&config_path, &mut *supervisor.lock().await).await {
+            Ok(()) => access::notice("✅ Configuration reloaded"),
+            Err(e) => stderr!("❌ Reload rejected, keeping current configuration: {:#}", e),
+        }
+    }
+}
+
+async fn reload(config_path: &Path, supervisor: &mut Supervisor) -> Result<()> {
+    // load_config falls back to defaults for a missing file; never reload into that
+    if !config_path.exists() {
+        return Err(anyhow::anyhow!("Config file not found: {}", config_path.display()));
+    }
+
+    let mut config = load_config(&config_path.to_path_buf())?;
+    config.apply_overrides();
+    supervisor.apply(&config, false).await
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
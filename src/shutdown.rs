@@ -0,0 +1,200 @@
+// Graceful shutdown: signal handling, connection tracking and draining
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Process exit code when connections were still open at the end of the drain timeout.
+pub const EXIT_DRAIN_TIMEOUT: i32 = 3;
+
+/// Every open client connection and UDP session across all listeners.
+#[derive(Debug, Default)]
+pub struct ConnectionTracker {
+    next_id: AtomicU64,
+    open: Mutex<BTreeMap<u64, Arc<ConnectionInfo>>>,
+    idle: Notify,
+    draining: AtomicBool,
+}
+
+/// One open connection, as listed by the admin API.
+#[derive(Debug)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub listener: String,
+    pub client: String,
+    pub started: Instant,
+    pub bytes_in: AtomicU64,  // Read from the client
+    pub bytes_out: AtomicU64, // Written to the client
+    target: Mutex<Option<String>>,
+    kill: Notify,
+    draining: AtomicBool,
+    drain: Notify,
+}
+
+impl ConnectionInfo {
+    /// The backend this connection was sent to, once one was picked.
+    pub fn target(&self) -> Option<String> {
+        self.target.lock().unwrap().clone()
+    }
+
+    pub fn set_target(&self, target: &impl fmt::Display) {
+        *self.target.lock().unwrap() = Some(target.to_string());
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed) + self.bytes_out.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once `ConnectionTracker::kill` was called for this connection.
+    pub async fn killed(&self) {
+        self.kill.notified().await
+    }
+
+    /// Whether the server is shutting down and idle keep-alive connections should close.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once `ConnectionTracker::start_draining` was called.
+    pub async fn draining(&self) {
+        self.drain.notified().await
+    }
+}
+
+impl ConnectionTracker {
+    pub fn new() -> Arc<ConnectionTracker> {
+        Arc::new(ConnectionTracker::default())
+    }
+
+    /// Register a connection until the guard is dropped. Connections tracked after
+    /// `start_draining` start out draining.
+    pub fn track(self: &Arc<Self>, listener: &str, client: &str) -> ConnectionGuard {
+        let info = Arc::new(ConnectionInfo {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            listener: listener.to_string(),
+            client: client.to_string(),
+            started: Instant::now(),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            target: Mutex::new(None),
+            kill: Notify::new(),
+            draining: AtomicBool::new(false),
+            drain: Notify::new(),
+        });
+        // Checked under the lock start_draining holds, so no connection slips past it
+        let mut open = self.open.lock().unwrap();
+        if self.draining.load(Ordering::Relaxed) {
+            info.draining.store(true, Ordering::Relaxed);
+            info.drain.notify_one();
+        }
+        open.insert(info.id, info.clone());
+        drop(open);
+        ConnectionGuard { tracker: self.clone(), info }
+    }
+
+    pub fn active(&self) -> usize {
+        self.open.lock().unwrap().len()
+    }
+
+    /// Open connections, oldest first.
+    pub fn list(&self) -> Vec<Arc<ConnectionInfo>> {
+        self.open.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Ask a connection's handler to stop. Returns `false` if no such connection is open.
+    pub fn kill(&self, id: u64) -> bool {
+        match self.open.lock().unwrap().get(&id) {
+            Some(info) => {
+                // notify_one keeps the permit if the handler is not waiting yet
+                info.kill.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tell every open connection that the server is shutting down, so HTTP
+    /// connections close once their current request is done and UDP sessions end.
+    pub fn start_draining(&self) {
+        let open = self.open.lock().unwrap();
+        self.draining.store(true, Ordering::Relaxed);
+        for info in open.values() {
+            info.draining.store(true, Ordering::Relaxed);
+            info.drain.notify_one();
+        }
+    }
+
+    /// Wait until no connections are open, or the timeout passes. Returns
+    /// `false` on timeout.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let idle = async {
+            loop {
+                // Register before checking so a drop in between still wakes us
+                let notified = self.idle.notified();
+                if self.active() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        tokio::time::timeout(timeout, idle).await.is_ok()
+    }
+}
+
+pub struct ConnectionGuard {
+    tracker: Arc<ConnectionTracker>,
+    info: Arc<ConnectionInfo>,
+}
+
+impl ConnectionGuard {
+    pub fn info(&self) -> &Arc<ConnectionInfo> {
+        &self.info
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut open = self.tracker.open.lock().unwrap();
+        open.remove(&self.info.id);
+        if open.is_empty() {
+            self.tracker.idle.notify_waiters();
+        }
+    }
+}
+
+/// Returned by `run_porty_server` when the drain timeout cut connections off.
+#[derive(Debug)]
+pub struct DrainTimeout {
+    pub remaining: usize,
+}
+
+impl fmt::Display for DrainTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "drain timeout reached with {} connection(s) still open", self.remaining)
+    }
+}
+
+impl std::error::Error for DrainTimeout {}
+
+/// Resolve on SIGTERM or Ctrl-C, with the signal's name.
+pub async fn shutdown_signal() -> Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        tokio::select! {
+            _ = terminate.recv() => Ok("SIGTERM"),
+            _ = interrupt.recv() => Ok("SIGINT"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("Ctrl-C")
+    }
+}
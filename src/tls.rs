@@ -0,0 +1,278 @@
+// TLS termination and SNI passthrough routing
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_rustls::rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use crate::balance::BackendPool;
+use crate::cfg::Route;
+use crate::net::Endpoint;
+use crate::vhost::normalize_host;
+
+// Upper bound on a reassembled ClientHello for passthrough routing
+const MAX_CLIENT_HELLO_SIZE: usize = 64 * 1024;
+
+/// An additional certificate served when the client's SNI matches `server_name`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TlsCertificate {
+    pub server_name: String, // Exact name or "*.example.com"
+    pub cert: String,        // PEM certificate chain path
+    pub key: String,         // PEM private key path
+}
+
+/// A passthrough backend chosen when the client's SNI matches `server_name`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SniTarget {
+    pub server_name: String, // Exact name or "*.example.com"
+    pub target: String,      // "host:port" or "unix:/path"
+}
+
+/// Exact match first, then a one-label wildcard: "*.example.com" covers "api.example.com".
+fn lookup_server_name<'a, T>(table: &'a HashMap<String, T>, server_name: &str) -> Option<&'a T> {
+    let server_name = server_name.to_ascii_lowercase();
+    if let Some(value) = table.get(&server_name) {
+        return Some(value);
+    }
+
+    let (_, parent) = server_name.split_once('.')?;
+    table.get(&format!("*.{}", parent))
+}
+
+/// Picks a certificate by SNI server name, falling back to the route's default.
+#[derive(Debug)]
+struct SniResolver {
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        client_hello
+            .server_name()
+            .and_then(|name| lookup_server_name(&self.by_name, name).cloned())
+            .or_else(|| self.default.clone())
+    }
+}
+
+/// SNI -> backend table for `mode = "tls-passthrough"` routes. Names without an
+/// entry fall back to the route's backend pool.
+#[derive(Debug)]
+pub struct SniRouter {
+    by_name: HashMap<String, Endpoint>,
+    pool: Arc<BackendPool>,
+}
+
+impl SniRouter {
+    pub fn from_route(route: &Route, pool: Arc<BackendPool>) -> Result<SniRouter> {
+        let mut by_name = HashMap::new();
+        for entry in &route.sni_targets {
+            let target = Endpoint::parse(&entry.target)
+                .context(format!("Invalid SNI target for '{}' on route '{}'", entry.server_name, route.name))?;
+            by_name.insert(entry.server_name.to_ascii_lowercase(), target);
+        }
+
+        Ok(SniRouter { by_name, pool })
+    }
+
+    pub fn select(&self, server_name: Option<&str>) -> Option<&Endpoint> {
+        server_name.and_then(|name| lookup_server_name(&self.by_name, name))
+    }
+
+    pub fn pool(&self) -> &BackendPool {
+        &self.pool
+    }
+}
+
+/// Build a TLS acceptor for the routes sharing a listener, or `None` if none of them
+/// configure certificates. Each route's `tls_cert` is also served for its `host` via
+/// SNI; the default route's certificate (or the first one found) answers everything else.
+pub fn build_acceptor(routes: &[Arc<Route>], default_route: Option<&Route>) -> Result<Option<TlsAcceptor>> {
+    let mut by_name = HashMap::new();
+    let mut first = None;
+    let mut default = None;
+
+    for route in routes {
+        let key = match (&route.tls_cert, &route.tls_key) {
+            (Some(cert), Some(key)) => Some(load_certified_key(cert, key)?),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Route '{}' must set both tls_cert and tls_key",
+                    route.name
+                ))
+            }
+        };
+
+        if let Some(key) = &key {
+            // Exact and wildcard host patterns double as SNI names; regexes cannot
+            let patterns = route.host.as_ref().map(|h| h.patterns()).unwrap_or_default();
+            for pattern in patterns.into_iter().filter(|p| !p.starts_with('~')) {
+                let name = normalize_host(pattern);
+                by_name.entry(name).or_insert_with(|| key.clone());
+            }
+            if default_route.map(|d| d.name == route.name).unwrap_or(false) {
+                default = Some(key.clone());
+            }
+            first.get_or_insert_with(|| key.clone());
+        }
+
+        for entry in &route.tls_certs {
+            let key = load_certified_key(&entry.cert, &entry.key)?;
+            by_name.insert(entry.server_name.to_ascii_lowercase(), key);
+        }
+    }
+
+    if first.is_none() && by_name.is_empty() {
+        return Ok(None);
+    }
+
+    let resolver = SniResolver { by_name, default: default.or(first) };
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+
+    if routes.iter().all(|r| r.mode == "http") {
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    }
+
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<Arc<CertifiedKey>> {
+    let mut cert_reader = BufReader::new(
+        File::open(cert_path).context(format!("Failed to open TLS certificate: {}", cert_path))?,
+    );
+    let certs = rustls_pemfile::certs(&mut cert_reader)
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("Failed to parse TLS certificate: {}", cert_path))?;
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!("No certificates found in {}", cert_path));
+    }
+
+    let mut key_reader = BufReader::new(
+        File::open(key_path).context(format!("Failed to open TLS key: {}", key_path))?,
+    );
+    let key = rustls_pemfile::private_key(&mut key_reader)
+        .context(format!("Failed to parse TLS key: {}", key_path))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", key_path))?;
+
+    let signing_key = any_supported_type(&key)
+        .map_err(|e| anyhow::anyhow!("Unsupported TLS key in {}: {}", key_path, e))?;
+
+    Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+}
+
+/// Read the TLS records carrying the ClientHello without decrypting anything.
+/// Returns the raw bytes (to replay to the backend) and the SNI server name, if any.
+pub async fn read_client_hello<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(Vec<u8>, Option<String>)> {
+    let mut raw = Vec::new();
+    let mut handshake = Vec::new();
+
+    loop {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).await.context("Connection closed before TLS ClientHello")?;
+        if header[0] != 0x16 {
+            return Err(anyhow::anyhow!("Not a TLS handshake (record type {:#04x})", header[0]));
+        }
+
+        let record_len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        raw.extend_from_slice(&header);
+        let start = raw.len();
+        raw.resize(start + record_len, 0);
+        stream.read_exact(&mut raw[start..]).await.context("Truncated TLS record")?;
+        handshake.extend_from_slice(&raw[start..]);
+
+        if handshake.len() >= 4 {
+            if handshake[0] != 0x01 {
+                return Err(anyhow::anyhow!("Expected TLS ClientHello, got handshake type {}", handshake[0]));
+            }
+            let message_len = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if message_len > MAX_CLIENT_HELLO_SIZE {
+                return Err(anyhow::anyhow!("TLS ClientHello too large ({} bytes)", message_len));
+            }
+            if handshake.len() >= 4 + message_len {
+                let server_name = parse_sni(&handshake[4..4 + message_len]);
+                return Ok((raw, server_name));
+            }
+        }
+
+        if raw.len() > MAX_CLIENT_HELLO_SIZE {
+            return Err(anyhow::anyhow!("TLS ClientHello too large"));
+        }
+    }
+}
+
+/// Extract the host_name entry of the server_name extension from a ClientHello body.
+pub fn parse_sni(client_hello: &[u8]) -> Option<String> {
+    let mut reader = ByteReader { data: client_hello, pos: 0 };
+
+    reader.skip(2 + 32)?; // legacy_version, random
+    let session_id_len = reader.u8()? as usize;
+    reader.skip(session_id_len)?;
+    let cipher_suites_len = reader.u16()? as usize;
+    reader.skip(cipher_suites_len)?;
+    let compression_len = reader.u8()? as usize;
+    reader.skip(compression_len)?;
+
+    let extensions_len = reader.u16()? as usize;
+    let mut extensions = ByteReader { data: reader.take(extensions_len)?, pos: 0 };
+
+    while let Some(extension_type) = extensions.u16() {
+        let extension_len = extensions.u16()? as usize;
+        let extension = extensions.take(extension_len)?;
+        if extension_type != 0x0000 {
+            continue;
+        }
+
+        let mut names = ByteReader { data: extension, pos: 0 };
+        let list_len = names.u16()? as usize;
+        let mut list = ByteReader { data: names.take(list_len)?, pos: 0 };
+        while let Some(name_type) = list.u8() {
+            let name_len = list.u16()? as usize;
+            let name = list.take(name_len)?;
+            if name_type == 0 {
+                return std::str::from_utf8(name).ok().map(|n| n.to_ascii_lowercase());
+            }
+        }
+        return None;
+    }
+
+    None
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
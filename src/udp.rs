@@ -0,0 +1,446 @@
+// UDP datagram forwarding with per-client sessions
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use crate::access::{self, classify, error_kind, AccessRecord, ErrorKind, LogFormat};
+use crate::acl;
+use crate::balance::{BackendLease, BackendPool, BalanceContext};
+use crate::cfg::Route;
+use crate::health::spawn_health_checks;
+use crate::metrics::{self, ActiveConnection, RouteMetrics};
+use crate::net::{format_bytes, Endpoint};
+use crate::shutdown::{ConnectionGuard, ConnectionInfo, ConnectionTracker};
+use rsb::prelude::*;
+
+// Maximum UDP payload size
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+struct UdpSession {
+    upstream: UdpSocket,
+    target: String,
+    last_seen: Mutex<Instant>,
+    // Listed by the admin API and waited for on shutdown; carries the byte counts
+    connection: ConnectionGuard,
+    // Each session holds a permit, so max_connections caps active sessions
+    _permit: OwnedSemaphorePermit,
+    // ...and its backend, so least-connections sees live sessions
+    _lease: BackendLease,
+    metrics: Arc<RouteMetrics>,
+    _active: ActiveConnection,
+}
+
+// Datagrams held for a client while its session connects; later ones are dropped
+const MAX_PENDING_DATAGRAMS: usize = 16;
+
+enum SessionSlot {
+    Opening(Arc<ConnectionInfo>, Vec<Vec<u8>>), // Resolving and connecting; datagrams wait here
+    Open(Arc<UdpSession>),
+}
+
+impl SessionSlot {
+    fn info(&self) -> &Arc<ConnectionInfo> {
+        match self {
+            SessionSlot::Opening(info, _) => info,
+            SessionSlot::Open(session) => session.connection.info(),
+        }
+    }
+}
+
+type SessionTable = Arc<Mutex<HashMap<SocketAddr, SessionSlot>>>;
+
+/// Settings a UDP listener applies to new sessions. Reloads swap in a new state;
+/// open sessions keep the backend they started with.
+pub struct UdpState {
+    pub listen_endpoint: Endpoint,
+    route: Arc<Route>,
+    pool: Arc<BackendPool>,
+    metrics: Arc<RouteMetrics>,
+    log_requests: bool,
+    log_format: LogFormat,
+    verbose: bool,
+}
+
+impl UdpState {
+    pub fn build(
+        route: Route,
+        listen_addr: &str,
+        log_requests: bool,
+        log_format: LogFormat,
+        verbose: bool,
+    ) -> Result<UdpState> {
+        let listen_endpoint = route.listen_endpoint(listen_addr)?;
+        let pool = Arc::new(BackendPool::from_route(&route)?);
+        if listen_endpoint.is_unix() || pool.backends().iter().any(|b| b.endpoint.is_unix()) {
+            return Err(anyhow::anyhow!("UDP routes only support host:port endpoints"));
+        }
+        if route.tls_cert.is_some() || !route.tls_certs.is_empty() {
+            return Err(anyhow::anyhow!("TLS is not supported on UDP routes"));
+        }
+        if route.send_proxy_protocol.is_some() || route.accept_proxy_protocol {
+            return Err(anyhow::anyhow!("PROXY protocol is not supported on UDP routes"));
+        }
+        // Probes connect over TCP, which would mark every UDP-only backend down
+        if route.health_check.is_some() {
+            return Err(anyhow::anyhow!("health_check is not supported on UDP routes"));
+        }
+
+        let metrics = metrics::route(&route.name);
+        Ok(UdpState { listen_endpoint, route: Arc::new(route), pool, metrics, log_requests, log_format, verbose })
+    }
+
+    pub fn start_health_checks(&self) -> Result<()> {
+        spawn_health_checks(&self.route, &self.pool)
+    }
+
+    pub async fn bind(&self) -> Result<UdpSocket> {
+        UdpSocket::bind(self.listen_endpoint.to_string())
+            .await
+            .context(format!("Failed to bind UDP socket to {}", self.listen_endpoint))
+    }
+}
+
+pub async fn run_udp_route(
+    route: &Route,
+    listen_addr: &str,
+    max_connections: usize,
+    log_requests: bool,
+    verbose: bool,
+) -> Result<()> {
+    let state = Arc::new(UdpState::build(route.clone(), listen_addr, log_requests, LogFormat::Default, verbose)?);
+    let socket = state.bind().await?;
+    state.start_health_checks()?;
+
+    // Nothing ever replaces the state, so keep the sender alive for the whole run
+    let (_state_tx, state_rx) = watch::channel(state);
+    serve_udp(socket, state_rx, max_connections, ConnectionTracker::new()).await
+}
+
+/// Relay datagrams until the state sender is dropped.
+pub async fn serve_udp(
+    socket: UdpSocket,
+    mut state_rx: watch::Receiver<Arc<UdpState>>,
+    max_connections: usize,
+    connections: Arc<ConnectionTracker>,
+) -> Result<()> {
+    let semaphore = Arc::new(Semaphore::new(max_connections));
+    let listener = Arc::new(socket);
+    let mut state = state_rx.borrow_and_update().clone();
+
+    log::info!("[{}] Listening on udp://{} -> {}", state.route.name, state.listen_endpoint, state.pool.label());
+
+    let sessions: SessionTable = Arc::new(Mutex::new(HashMap::new()));
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+
+    loop {
+        let (n, client_addr) = tokio::select! {
+            received = listener.recv_from(&mut buffer) => received?,
+            changed = state_rx.changed() => {
+                if changed.is_err() {
+                    log::info!("[{}] Stopped listening on udp://{}", state.route.name, state.listen_endpoint);
+                    // Sessions share the socket; end them so the port is free for the
+                    // next listener. On shutdown the drain ends them instead.
+                    for slot in sessions.lock().unwrap().values() {
+                        if !slot.info().is_draining() {
+                            connections.kill(slot.info().id);
+                        }
+                    }
+                    return Ok(());
+                }
+                state = state_rx.borrow_and_update().clone();
+                continue;
+            }
+        };
+        let route = &state.route;
+
+        let open = match sessions.lock().unwrap().get_mut(&client_addr) {
+            Some(SessionSlot::Open(session)) => Some(session.clone()),
+            Some(SessionSlot::Opening(_, pending)) => {
+                // Still connecting: queue behind the datagrams already waiting
+                if pending.len() < MAX_PENDING_DATAGRAMS {
+                    pending.push(buffer[..n].to_vec());
+                }
+                continue;
+            }
+            None => None,
+        };
+
+        let session = match open {
+            Some(session) => {
+                *session.last_seen.lock().unwrap() = Instant::now();
+                session
+            }
+            None => {
+                if !acl::permits(&route.allow, &route.deny, client_addr.ip()) {
+                    let message = format!("🚫 [{}] Rejected datagram from {} by access list", route.name, client_addr);
+                    drop_datagram(&state, client_addr, ErrorKind::Forbidden, message);
+                    continue;
+                }
+
+                let permit = match semaphore.clone().try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        let message = format!(
+                            "⚠️  [{}] Session limit ({}) reached, dropping datagram from {}",
+                            route.name, max_connections, client_addr
+                        );
+                        drop_datagram(&state, client_addr, ErrorKind::Overloaded, message);
+                        continue;
+                    }
+                };
+
+                let lease = match state.pool.select(&BalanceContext { client_ip: Some(client_addr.ip()), header_value: None }) {
+                    Some(lease) => lease,
+                    None => {
+                        let message = format!("⚠️  [{}] No healthy backend available, dropping datagram from {}", route.name, client_addr);
+                        drop_datagram(&state, client_addr, ErrorKind::NoBackend, message);
+                        continue;
+                    }
+                };
+
+                // Resolving the backend can be slow; existing sessions keep flowing meanwhile
+                let connection = connections.track(&route.name, &client_addr.to_string());
+                connection.info().set_target(lease.endpoint());
+                let slot = SessionSlot::Opening(connection.info().clone(), vec![buffer[..n].to_vec()]);
+                sessions.lock().unwrap().insert(client_addr, slot);
+                tokio::spawn(open_session(
+                    listener.clone(),
+                    client_addr,
+                    sessions.clone(),
+                    state.clone(),
+                    connection,
+                    permit,
+                    lease,
+                ));
+                continue;
+            }
+        };
+
+        send_datagram(&state, &session, client_addr, &buffer[..n]).await;
+    }
+}
+
+/// Connect a new session's upstream socket, send the datagrams that arrived in
+/// the meantime, then relay replies until the session idles out.
+async fn open_session(
+    listener: Arc<UdpSocket>,
+    client_addr: SocketAddr,
+    sessions: SessionTable,
+    state: Arc<UdpState>,
+    connection: ConnectionGuard,
+    permit: OwnedSemaphorePermit,
+    lease: BackendLease,
+) {
+    let route = &state.route;
+    let structured = state.log_requests && state.log_format.is_structured();
+    let records = state.log_requests && access::wants_records(state.log_format, &route.name);
+    let target_addr_full = lease.endpoint().to_string();
+
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+    if state.log_requests && !structured {
+        echo!(
+            "🔄 [{}] {} | New session from {} -> forwarding to {}",
+            route.name, timestamp, client_addr, target_addr_full
+        );
+    }
+
+    let connect_start = Instant::now();
+    let connect_timeout = Duration::from_secs(route.timeout_seconds);
+    let connected = match tokio::time::timeout(connect_timeout, connect_upstream(&target_addr_full)).await {
+        Ok(connected) => connected.map_err(|e| (ErrorKind::Connect, e)),
+        Err(_) => {
+            state.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+            Err((ErrorKind::Timeout, anyhow::anyhow!("Timed out connecting to target {}", target_addr_full)))
+        }
+    };
+    let upstream = match connected {
+        Ok(upstream) => {
+            state.metrics.connect_duration.observe(connect_start.elapsed());
+            upstream
+        }
+        Err((kind, e)) => {
+            // Datagrams queued for the session go with it
+            sessions.lock().unwrap().remove(&client_addr);
+            state.metrics.connect_failures.fetch_add(1, Ordering::Relaxed);
+            if records {
+                let mut record = AccessRecord::new(&route.name, &client_addr.to_string());
+                record.target = Some(target_addr_full);
+                record.error = Some(kind);
+                record.emit(state.log_format, connect_start.elapsed());
+            }
+            if !structured {
+                stderr!(
+                    "❌ [{}] {} | Connection error for {}: {}",
+                    route.name, timestamp, client_addr, e
+                );
+            }
+            return;
+        }
+    };
+
+    let session = Arc::new(UdpSession {
+        upstream,
+        target: target_addr_full,
+        last_seen: Mutex::new(Instant::now()),
+        connection,
+        _permit: permit,
+        _lease: lease,
+        metrics: state.metrics.clone(),
+        _active: state.metrics.connection_opened(),
+    });
+    let pending = match sessions.lock().unwrap().insert(client_addr, SessionSlot::Open(session.clone())) {
+        Some(SessionSlot::Opening(_, pending)) => pending,
+        _ => vec![],
+    };
+    for datagram in pending {
+        send_datagram(&state, &session, client_addr, &datagram).await;
+    }
+
+    relay_replies(listener, client_addr, sessions, session, state).await;
+}
+
+async fn send_datagram(state: &UdpState, session: &UdpSession, client_addr: SocketAddr, datagram: &[u8]) {
+    match session.upstream.send(datagram).await {
+        Ok(sent) => {
+            session.connection.info().bytes_in.fetch_add(sent as u64, Ordering::Relaxed);
+            session.metrics.bytes_received.fetch_add(sent as u64, Ordering::Relaxed);
+        }
+        Err(e) => {
+            if !(state.log_requests && state.log_format.is_structured()) {
+                let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+                stderr!(
+                    "❌ [{}] {} | Connection error for {}: {}",
+                    state.route.name, timestamp, client_addr, e
+                );
+            }
+        }
+    }
+}
+
+/// Count and log a datagram from a new client that gets no session. Refusals
+/// by the access lists and the session limit count as rejected connections.
+fn drop_datagram(state: &UdpState, client_addr: SocketAddr, kind: ErrorKind, message: String) {
+    if matches!(kind, ErrorKind::Forbidden | ErrorKind::Overloaded) {
+        state.metrics.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+    if state.log_requests && access::wants_records(state.log_format, &state.route.name) {
+        let mut record = AccessRecord::new(&state.route.name, &client_addr.to_string());
+        record.error = Some(kind);
+        record.emit(state.log_format, Duration::ZERO);
+    }
+    if !(state.log_requests && state.log_format.is_structured()) {
+        stderr!("{}", message);
+    }
+}
+
+async fn connect_upstream(target_addr: &str) -> Result<UdpSocket> {
+    let target = tokio::net::lookup_host(target_addr)
+        .await
+        .context(format!("Failed to resolve target {}", target_addr))?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No address found for target {}", target_addr))?;
+
+    let bind_addr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let upstream = UdpSocket::bind(bind_addr).await?;
+    upstream
+        .connect(target)
+        .await
+        .context(format!("Failed to connect to target {}", target_addr))?;
+
+    Ok(upstream)
+}
+
+/// Relay backend replies to the client until the session idles out. The state is
+/// the one the session started under.
+async fn relay_replies(
+    listener: Arc<UdpSocket>,
+    client_addr: SocketAddr,
+    sessions: SessionTable,
+    session: Arc<UdpSession>,
+    state: Arc<UdpState>,
+) {
+    let route_name = &state.route.name;
+    let info = session.connection.info();
+    let idle_timeout = Duration::from_secs(state.route.session_timeout_seconds);
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    let mut error: Option<anyhow::Error> = None;
+
+    loop {
+        let idle_deadline = *session.last_seen.lock().unwrap() + idle_timeout;
+
+        tokio::select! {
+            result = session.upstream.recv(&mut buffer) => {
+                let n = match result {
+                    Ok(n) => n,
+                    Err(e) => {
+                        error = Some(e.into());
+                        break;
+                    }
+                };
+                if let Err(e) = listener.send_to(&buffer[..n], client_addr).await {
+                    error = Some(e.into());
+                    break;
+                }
+                info.bytes_out.fetch_add(n as u64, Ordering::Relaxed);
+                session.metrics.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+                *session.last_seen.lock().unwrap() = Instant::now();
+            }
+            _ = tokio::time::sleep_until(idle_deadline.into()) => {
+                // Client traffic may have refreshed the session while we slept
+                if session.last_seen.lock().unwrap().elapsed() >= idle_timeout {
+                    break;
+                }
+            }
+            _ = info.killed() => {
+                error = Some(classify(ErrorKind::Killed, anyhow::anyhow!("Closed via admin API")));
+                break;
+            }
+            // The listener has stopped, so no more client datagrams will arrive
+            _ = info.draining() => break,
+        }
+    }
+
+    sessions.lock().unwrap().remove(&client_addr);
+
+    let bytes_in = info.bytes_in.load(Ordering::Relaxed);
+    let bytes_out = info.bytes_out.load(Ordering::Relaxed);
+    if state.log_requests && access::wants_records(state.log_format, route_name) {
+        let mut record = AccessRecord::new(route_name, &client_addr.to_string());
+        record.target = Some(session.target.clone());
+        record.bytes_in = bytes_in;
+        record.bytes_out = bytes_out;
+        record.error = error.as_ref().map(error_kind);
+        record.emit(state.log_format, info.started.elapsed());
+    }
+    if state.log_requests && state.log_format.is_structured() {
+        return;
+    }
+
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+    match error {
+        None => {
+            if state.verbose {
+                echo!(
+                    "✅ [{}] {} | Session expired: {} | Duration: {:.2}s | Transferred: {} bytes",
+                    route_name,
+                    timestamp,
+                    client_addr,
+                    info.started.elapsed().as_secs_f64(),
+                    format_bytes(bytes_in + bytes_out)
+                );
+            }
+        }
+        Some(e) => {
+            stderr!(
+                "❌ [{}] {} | Connection error for {}: {}",
+                route_name, timestamp, client_addr, e
+            );
+        }
+    }
+}
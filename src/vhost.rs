@@ -0,0 +1,178 @@
+// Virtual host table for HTTP routes sharing a listener
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::balance::BackendPool;
+use crate::cfg::Route;
+
+/// The `host` setting: one pattern or a list. Patterns are exact names,
+/// wildcards like `*.example.com`, or regexes prefixed with `~`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HostList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl HostList {
+    pub fn patterns(&self) -> Vec<&str> {
+        match self {
+            HostList::One(host) => vec![host.as_str()],
+            HostList::Many(hosts) => hosts.iter().map(|h| h.as_str()).collect(),
+        }
+    }
+}
+
+/// Lowercase a Host header value and drop its port and any trailing dot.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = if let Some(rest) = host.strip_prefix('[') {
+        // Bracketed IPv6 literal, optionally followed by :port
+        rest.split(']').next().unwrap_or(rest)
+    } else {
+        match host.rsplit_once(':') {
+            Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+            _ => host,
+        }
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Which route served a request, and whether it was chosen by Host header.
+pub struct VirtualHostMatch<'a> {
+    pub route: &'a Arc<Route>,
+    pub pool: &'a Arc<BackendPool>,
+    pub host_matched: bool,
+}
+
+#[derive(Debug)]
+pub struct VirtualHosts {
+    routes: Vec<Arc<Route>>,
+    pools: Vec<Arc<BackendPool>>, // Parallel to routes
+    default: Option<usize>,
+    exact: HashMap<String, usize>,
+    wildcards: Vec<(String, usize)>, // ".example.com" suffixes, longest first
+    regexes: Vec<(Regex, usize)>,
+}
+
+impl VirtualHosts {
+    /// Build the table for routes bound to the same address. Unmatched hosts go to the
+    /// route marked `default = true`, else the only route without a `host`, else the
+    /// route itself when it is alone on the port.
+    pub fn new(routes: Vec<Arc<Route>>) -> Result<VirtualHosts> {
+        let explicit: Vec<usize> = routes.iter()
+            .enumerate()
+            .filter(|(_, r)| r.default)
+            .map(|(i, _)| i)
+            .collect();
+
+        if explicit.len() > 1 {
+            let names: Vec<&str> = explicit.iter().map(|&i| routes[i].name.as_str()).collect();
+            return Err(anyhow::anyhow!(
+                "Only one route per listen address can set default = true (found: {})",
+                names.join(", ")
+            ));
+        }
+
+        let hostless: Vec<usize> = routes.iter()
+            .enumerate()
+            .filter(|(_, r)| r.host.is_none())
+            .map(|(i, _)| i)
+            .collect();
+
+        let default = if let Some(&i) = explicit.first() {
+            Some(i)
+        } else if hostless.len() == 1 {
+            Some(hostless[0])
+        } else if routes.len() == 1 {
+            Some(0)
+        } else {
+            None
+        };
+
+        let pools = routes.iter()
+            .map(|route| BackendPool::from_route(route).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut exact: HashMap<String, usize> = HashMap::new();
+        let mut wildcards = Vec::new();
+        let mut regexes = Vec::new();
+
+        for (i, route) in routes.iter().enumerate() {
+            let patterns = route.host.as_ref().map(|h| h.patterns()).unwrap_or_default();
+            for pattern in patterns {
+                if let Some(expr) = pattern.strip_prefix('~') {
+                    // Anchored: a pattern must match the whole host, not part of it
+                    let regex = Regex::new(&format!("(?i)^(?:{})$", expr))
+                        .context(format!("Invalid host regex '{}' on route '{}'", expr, route.name))?;
+                    regexes.push((regex, i));
+                } else if let Some(suffix) = pattern.strip_prefix("*.") {
+                    wildcards.push((format!(".{}", normalize_host(suffix)), i));
+                } else if let Some(&other) = exact.get(&normalize_host(pattern)) {
+                    return Err(anyhow::anyhow!(
+                        "Host '{}' is claimed by both route '{}' and route '{}'",
+                        pattern, routes[other].name, route.name
+                    ));
+                } else {
+                    exact.insert(normalize_host(pattern), i);
+                }
+            }
+        }
+
+        // Most specific wildcard wins
+        wildcards.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+
+        Ok(VirtualHosts { routes, pools, default, exact, wildcards, regexes })
+    }
+
+    pub fn routes(&self) -> &[Arc<Route>] {
+        &self.routes
+    }
+
+    pub fn pools(&self) -> &[Arc<BackendPool>] {
+        &self.pools
+    }
+
+    pub fn default_route(&self) -> Option<&Arc<Route>> {
+        self.default.map(|i| &self.routes[i])
+    }
+
+    /// Backend pool of the default route, else of the first route.
+    pub fn default_pool(&self) -> &Arc<BackendPool> {
+        &self.pools[self.default.unwrap_or(0)]
+    }
+
+    /// Pick the route for an incoming Host header value: exact names first,
+    /// then wildcards, then regexes in config order.
+    pub fn select(&self, host: Option<&str>) -> Option<VirtualHostMatch<'_>> {
+        if let Some(incoming) = host {
+            if let Some(i) = self.match_host(&normalize_host(incoming)) {
+                return Some(VirtualHostMatch { route: &self.routes[i], pool: &self.pools[i], host_matched: true });
+            }
+        }
+
+        self.default.map(|i| VirtualHostMatch { route: &self.routes[i], pool: &self.pools[i], host_matched: false })
+    }
+
+    fn match_host(&self, host: &str) -> Option<usize> {
+        if let Some(&i) = self.exact.get(host) {
+            return Some(i);
+        }
+
+        // "*.example.com" covers one label, like certificate wildcards (RFC 6125)
+        let wildcard = self.wildcards.iter().find(|(suffix, _)| {
+            host.strip_suffix(suffix.as_str())
+                .is_some_and(|label| !label.is_empty() && !label.contains('.'))
+        });
+        if let Some(&(_, i)) = wildcard {
+            return Some(i);
+        }
+
+        self.regexes.iter()
+            .find(|(regex, _)| regex.is_match(host))
+            .map(|&(_, i)| i)
+    }
+}
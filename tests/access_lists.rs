@@ -0,0 +1,74 @@
+// Allow/deny list tests
+
+use porty::acl::{admits, permits, Cidr};
+use porty::{Config, Route};
+
+fn cidrs(specs: &[&str]) -> Vec<Cidr> {
+    specs.iter().map(|spec| Cidr::parse(spec).unwrap()).collect()
+}
+
+#[test]
+fn test_cidr_matching() {
+    let private = Cidr::parse("10.0.0.0/8").unwrap();
+    assert!(private.contains("10.200.3.4".parse().unwrap()));
+    assert!(!private.contains("11.0.0.1".parse().unwrap()));
+    assert!(private.contains("::ffff:10.1.2.3".parse().unwrap()));
+
+    let host = Cidr::parse("192.168.1.7").unwrap();
+    assert_eq!(host.to_string(), "192.168.1.7/32");
+    assert!(!host.contains("192.168.1.8".parse().unwrap()));
+
+    assert!(Cidr::parse("fd00::/8").unwrap().contains("fd12::1".parse().unwrap()));
+    assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+    assert!(!Cidr::parse("0.0.0.0/0").unwrap().contains("::1".parse().unwrap()));
+
+    assert!(Cidr::parse("10.0.0.0/33").is_err());
+    assert!(Cidr::parse("example.com/8").is_err());
+
+    println!("✅ CIDR ranges match IPv4 and IPv6 clients");
+}
+
+#[test]
+fn test_deny_wins_over_allow() {
+    let allow = cidrs(&["10.0.0.0/8"]);
+    let deny = cidrs(&["10.66.0.0/16"]);
+    assert!(permits(&allow, &deny, "10.1.1.1".parse().unwrap()));
+    assert!(!permits(&allow, &deny, "10.66.1.1".parse().unwrap()));
+    assert!(!permits(&allow, &deny, "192.0.2.1".parse().unwrap()));
+    assert!(permits(&[], &deny, "192.0.2.1".parse().unwrap()));
+
+    println!("✅ Deny entries override allow entries");
+}
+
+#[test]
+fn test_global_lists_layer_under_routes() {
+    let config: Config = toml::from_str(
+        r#"
+        allow = ["10.0.0.0/8"]
+        deny = ["10.9.9.9"]
+
+        [[routes]]
+        name = "admin"
+        allow = ["127.0.0.1"]
+        deny = ["127.0.0.2"]
+
+        [[routes]]
+        name = "web"
+        "#,
+    )
+    .unwrap();
+
+    let admin = config.with_global_lists(config.routes[0].clone());
+    assert!(admits(&admin, "127.0.0.1:4000"));
+    assert!(!admits(&admin, "10.1.1.1:4000"));
+    assert_eq!(admin.deny.len(), 2);
+
+    let web = config.with_global_lists(config.routes[1].clone());
+    assert!(admits(&web, "10.1.1.1:4000"));
+    assert!(!admits(&web, "10.9.9.9:4000"));
+    assert!(admits(&web, "unix:/run/client.sock"));
+
+    assert!(toml::from_str::<Route>("name = \"bad\"\nallow = [\"10.0.0.0/40\"]").is_err());
+
+    println!("✅ Route lists layer over the global lists");
+}
@@ -0,0 +1,53 @@
+// Structured access log tests
+
+use anyhow::Context;
+use porty::access::{classify, error_kind, AccessRecord, ErrorKind, LogFormat};
+
+#[test]
+fn test_log_format_parsing() {
+    assert_eq!(LogFormat::parse("default").unwrap(), LogFormat::Default);
+    assert_eq!(LogFormat::parse("json").unwrap(), LogFormat::Json);
+    assert_eq!(LogFormat::parse("logfmt").unwrap(), LogFormat::Logfmt);
+    assert!(LogFormat::parse("xml").is_err());
+    assert!(!LogFormat::Default.is_structured());
+
+    println!("✅ Log formats parse");
+}
+
+#[test]
+fn test_record_serialization() {
+    let mut record = AccessRecord::new("web", "10.0.0.1:5000");
+    record.timestamp = "2025-09-20T20:45:23.123+00:00".to_string();
+    record.method = Some("GET".to_string());
+    record.path = Some("/search results".to_string());
+    record.status = Some(502);
+    record.retries = 2;
+    record.error = Some(ErrorKind::Connect);
+
+    assert_eq!(
+        record.to_logfmt(),
+        "timestamp=2025-09-20T20:45:23.123+00:00 route=web client=10.0.0.1:5000 method=GET \
+         path=\"/search results\" status=502 bytes_in=0 bytes_out=0 duration_ms=0 retries=2 error=connect"
+    );
+
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(json["route"], "web");
+    assert_eq!(json["status"], 502);
+    assert_eq!(json["error"], "connect");
+    assert!(json["target"].is_null());
+
+    println!("✅ Access records serialize as logfmt and JSON");
+}
+
+#[test]
+fn test_error_kind_survives_context() {
+    let error = classify(ErrorKind::Timeout, anyhow::anyhow!("Request timeout after 5 seconds"));
+    assert_eq!(error.to_string(), "Request timeout after 5 seconds");
+
+    let wrapped: anyhow::Result<()> = Err(error);
+    let wrapped = wrapped.context("Failed to reach backend").unwrap_err();
+    assert_eq!(error_kind(&wrapped), ErrorKind::Timeout);
+    assert_eq!(error_kind(&anyhow::anyhow!("plain")), ErrorKind::Io);
+
+    println!("✅ Error kinds survive added context");
+}
@@ -0,0 +1,51 @@
+// Connection tracker tests
+
+use porty::shutdown::ConnectionTracker;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_tracker_lists_and_kills_connections() {
+    let tracker = ConnectionTracker::new();
+    let first = tracker.track("web", "10.0.0.1:5000");
+    let second = tracker.track("ssh", "10.0.0.2:6000");
+    first.info().bytes_in.fetch_add(40, Ordering::Relaxed);
+    first.info().bytes_out.fetch_add(2, Ordering::Relaxed);
+    first.info().set_target(&"10.0.1.1:80");
+
+    let open = tracker.list();
+    assert_eq!(open.len(), 2);
+    assert_eq!(open[0].listener, "web");
+    assert_eq!(open[0].client, "10.0.0.1:5000");
+    assert_eq!(open[0].bytes(), 42);
+    assert_eq!(open[0].target().as_deref(), Some("10.0.1.1:80"));
+    assert_eq!(open[1].target(), None);
+
+    // A kill before the handler waits is not lost
+    assert!(tracker.kill(second.info().id));
+    tokio::time::timeout(Duration::from_secs(1), second.info().killed()).await.unwrap();
+    assert!(!tracker.kill(999));
+
+    drop(second);
+    assert_eq!(tracker.active(), 1);
+    assert!(!tracker.drain(Duration::from_millis(20)).await);
+    drop(first);
+    assert!(tracker.drain(Duration::from_millis(20)).await);
+
+    println!("✅ Connection tracker lists and kills connections");
+}
+
+#[tokio::test]
+async fn test_late_connections_start_draining() {
+    let tracker = ConnectionTracker::new();
+    let before = tracker.track("web", "10.0.0.1:5000");
+    tracker.start_draining();
+    assert!(before.info().is_draining());
+
+    // Accepted just before the listener stopped, tracked just after the signal
+    let late = tracker.track("dns", "10.0.0.2:5353");
+    assert!(late.info().is_draining());
+    tokio::time::timeout(Duration::from_secs(1), late.info().draining()).await.unwrap();
+
+    println!("✅ Late connections start out draining");
+}
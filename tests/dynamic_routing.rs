@@ -0,0 +1,62 @@
+// Dynamic routing policy tests
+
+use porty::access::{error_kind, ErrorKind};
+use porty::acl::Cidr;
+use porty::dynamic::{DynamicRouting, PortRange};
+use porty::Endpoint;
+
+#[test]
+fn test_port_ranges() {
+    let range = PortRange::parse("8000-8999").unwrap();
+    assert!(range.contains(8000) && range.contains(8999));
+    assert!(!range.contains(9000));
+    assert_eq!(PortRange::parse("443").unwrap(), PortRange { start: 443, end: 443 });
+    assert!(PortRange::parse("9000-8000").is_err());
+
+    let policy: DynamicRouting = toml::from_str("allow_ports = [443, \"8000-8999\"]").unwrap();
+    assert!(policy.enabled);
+    assert_eq!(policy.allow_ports[1], range);
+
+    println!("✅ Port ranges parse from numbers and strings");
+}
+
+#[tokio::test]
+async fn test_local_targets_are_refused() {
+    let policy = DynamicRouting::default();
+    for target in ["127.0.0.1:80", "169.254.169.254:80", "[::1]:80", "0.0.0.0:80", "[fe80::1]:80"] {
+        let error = policy.authorize(&Endpoint::parse(target).unwrap()).await.unwrap_err();
+        assert_eq!(error_kind(&error), ErrorKind::Forbidden, "{}", target);
+    }
+    assert!(policy.authorize(&Endpoint::parse("10.1.2.3:80").unwrap()).await.is_ok());
+
+    // Listing a range explicitly lets loopback through
+    let dev = DynamicRouting { allow_cidrs: vec![Cidr::parse("127.0.0.0/8").unwrap()], ..DynamicRouting::default() };
+    let address = dev.authorize(&Endpoint::parse("127.0.0.1:8000").unwrap()).await.unwrap();
+    assert_eq!(address.to_string(), "127.0.0.1:8000");
+
+    println!("✅ Loopback and link-local targets are refused unless listed");
+}
+
+#[tokio::test]
+async fn test_allow_lists_restrict_targets() {
+    let policy = DynamicRouting {
+        allow_hosts: vec!["*.example.com".to_string()],
+        allow_cidrs: vec![Cidr::parse("10.0.0.0/8").unwrap()],
+        allow_ports: vec![PortRange::parse("80").unwrap()],
+        ..DynamicRouting::default()
+    };
+    assert!(policy.authorize(&Endpoint::parse("10.9.8.7:80").unwrap()).await.is_ok());
+
+    // Wildcards cover one label, like virtual host and SNI names
+    assert!(policy.allows_host("api.example.com"));
+    assert!(policy.allows_host("API.Example.com."));
+    assert!(!policy.allows_host("a.b.example.com"));
+    assert!(!policy.allows_host("example.com"));
+
+    let error = policy.authorize(&Endpoint::parse("192.0.2.10:80").unwrap()).await.unwrap_err();
+    assert_eq!(error_kind(&error), ErrorKind::Forbidden);
+    let error = policy.authorize(&Endpoint::parse("10.9.8.7:22").unwrap()).await.unwrap_err();
+    assert!(error.to_string().contains("port 22 is not allowed"));
+
+    println!("✅ Allow lists restrict dynamic targets");
+}
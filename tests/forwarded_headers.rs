@@ -0,0 +1,64 @@
+// Client identity header tests
+
+use porty::forwarded::{forwarded_element, ForwardedHeaders};
+use porty::headers::Headers;
+
+fn all_headers(trusted: &str) -> ForwardedHeaders {
+    toml::from_str(&format!(
+        "x_forwarded_for = true\nx_forwarded_proto = true\nx_forwarded_host = true\n\
+         x_real_ip = true\nforwarded = true\ntrusted_proxies = [\"{}\"]",
+        trusted
+    ))
+    .unwrap()
+}
+
+fn spoofed() -> Headers {
+    let mut headers = Headers::new();
+    headers.append("Host", "app.example.com");
+    headers.append("X-Forwarded-For", "1.2.3.4");
+    headers.append("X-Real-IP", "1.2.3.4");
+    headers.append("X-Forwarded-Proto", "https");
+    headers.append("Forwarded", "for=1.2.3.4");
+    headers
+}
+
+#[test]
+fn test_untrusted_values_are_replaced() {
+    let mut headers = spoofed();
+    all_headers("10.0.0.0/8").apply(&mut headers, "203.0.113.7:51000", "http");
+
+    assert_eq!(headers.get("X-Forwarded-For"), Some("203.0.113.7"));
+    assert_eq!(headers.get("X-Real-IP"), Some("203.0.113.7"));
+    assert_eq!(headers.get("X-Forwarded-Proto"), Some("http"));
+    assert_eq!(headers.get("X-Forwarded-Host"), Some("app.example.com"));
+    assert_eq!(headers.get("Forwarded"), Some("for=203.0.113.7;proto=http;host=app.example.com"));
+
+    println!("✅ Headers from untrusted clients are replaced");
+}
+
+#[test]
+fn test_trusted_proxies_are_extended() {
+    let mut headers = spoofed();
+    all_headers("10.0.0.0/8").apply(&mut headers, "10.1.2.3:40000", "http");
+
+    assert_eq!(headers.get("X-Forwarded-For"), Some("1.2.3.4, 10.1.2.3"));
+    assert_eq!(headers.get("X-Real-IP"), Some("1.2.3.4"));
+    assert_eq!(headers.get("X-Forwarded-Proto"), Some("https"));
+    assert_eq!(headers.get("Forwarded"), Some("for=1.2.3.4, for=10.1.2.3;proto=http;host=app.example.com"));
+
+    // Disabled headers pass through untouched
+    let mut headers = spoofed();
+    ForwardedHeaders::default().apply(&mut headers, "203.0.113.7:51000", "http");
+    assert_eq!(headers, spoofed());
+
+    println!("✅ Trusted proxies keep their chain");
+}
+
+#[test]
+fn test_forwarded_element_quoting() {
+    let ipv6 = "2001:db8::1".parse().ok();
+    assert_eq!(forwarded_element(ipv6, "https", Some("example.com:8443")), "for=\"[2001:db8::1]\";proto=https;host=\"example.com:8443\"");
+    assert_eq!(forwarded_element(None, "http", None), "for=unknown;proto=http");
+
+    println!("✅ Forwarded elements quote IPv6 and ports");
+}
@@ -0,0 +1,40 @@
+// Ordered header list tests
+
+use porty::headers::Headers;
+
+#[test]
+fn test_headers_keep_order_case_and_repeats() {
+    let mut headers = Headers::new();
+    headers.append("Host", "app.example.com");
+    headers.append("Cookie", "a=1");
+    headers.append("X-Legacy-Token", "secret");
+    headers.append("cookie", "b=2");
+
+    assert_eq!(headers.get("HOST"), Some("app.example.com"));
+    assert_eq!(headers.get_all("Cookie").collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+    assert_eq!(
+        headers.to_wire(),
+        "Host: app.example.com\r\nCookie: a=1\r\nX-Legacy-Token: secret\r\ncookie: b=2\r\n"
+    );
+
+    println!("✅ Headers keep order, case and repeats");
+}
+
+#[test]
+fn test_set_replaces_in_place() {
+    let mut headers = Headers::new();
+    headers.append("accept", "*/*");
+    headers.append("host", "old");
+    headers.append("X-Trace", "1");
+    headers.append("Host", "duplicate");
+
+    headers.set("Host", "backend.internal");
+    assert_eq!(headers.to_wire(), "accept: */*\r\nhost: backend.internal\r\nX-Trace: 1\r\n");
+
+    headers.set("Via", "porty");
+    headers.remove("x-trace");
+    assert_eq!(headers.iter().map(|(name, _)| name).collect::<Vec<_>>(), vec!["accept", "host", "Via"]);
+    assert!(!headers.contains("X-Trace"));
+
+    println!("✅ Setting a header keeps its position and casing");
+}
@@ -0,0 +1,44 @@
+// Host header policy tests for HTTP routes
+
+use porty::cfg::Route;
+use porty::http::HostHeader;
+use porty::net::Endpoint;
+
+#[test]
+fn test_host_header_values() {
+    let tcp = Endpoint::tcp("10.0.0.5", 8080);
+    let ipv6 = Endpoint::tcp("2001:db8::5", 8080);
+    let unix = Endpoint::parse("unix:/run/app.sock").unwrap();
+
+    assert_eq!(HostHeader::Target.value(&tcp, Some("app.example.com")), "10.0.0.5:8080");
+    assert_eq!(HostHeader::Target.value(&ipv6, None), "[2001:db8::5]:8080");
+    assert_eq!(HostHeader::Preserve.value(&tcp, Some("app.example.com:8443")), "app.example.com:8443");
+    let custom = HostHeader::Custom("api.internal".to_string());
+    assert_eq!(custom.value(&tcp, Some("app.example.com")), "api.internal");
+
+    // Without a client Host, preserve falls back to the target
+    assert_eq!(HostHeader::Preserve.value(&tcp, None), "10.0.0.5:8080");
+    // Unix sockets have no host name to send
+    assert_eq!(HostHeader::Target.value(&unix, Some("app.example.com")), "app.example.com");
+    assert_eq!(HostHeader::Target.value(&unix, None), "localhost");
+
+    println!("✅ Host header policies pick the right value");
+}
+
+#[test]
+fn test_host_header_config() {
+    assert_eq!(HostHeader::parse("preserve").unwrap(), HostHeader::Preserve);
+    assert_eq!(HostHeader::parse("custom:api.internal:9000").unwrap(), HostHeader::Custom("api.internal:9000".to_string()));
+    assert!(HostHeader::parse("custom:").is_err());
+    assert!(HostHeader::parse("custom:evil\r\nX-Injected: 1").is_err());
+    assert!(HostHeader::parse("original").is_err());
+
+    let route: Route = toml::from_str(
+        "name = \"app\"\nlisten_port = 8080\nmode = \"http\"\nhost_header = \"custom:api.internal\"",
+    )
+    .unwrap();
+    assert_eq!(route.host_header, HostHeader::Custom("api.internal".to_string()));
+    assert_eq!(Route::default().host_header, HostHeader::Target);
+
+    println!("✅ host_header settings parse and default to target");
+}
@@ -0,0 +1,379 @@
+// HTTP message framing and keep-alive tests
+
+use porty::access::{error_kind, ErrorKind, LogFormat};
+use porty::framing::{self, BodyFraming};
+use porty::handle_http_connection;
+use porty::headers::Headers;
+use porty::shutdown::ConnectionTracker;
+use porty::vhost::VirtualHosts;
+use porty::Route;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+// Backend that answers each request with its path and body size, and never closes first.
+// "/chunked" gets the answer as a chunked body, "/both" as one that also has a Content-Length.
+// Upgrade requests switch to an echo tunnel.
+async fn spawn_backend() -> u16 {
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = backend.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = backend.accept().await {
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Ok(head) = framing::read_head(&mut stream).await {
+                    let headers = framing::parse_fields(&head[1..]).unwrap();
+                    if headers.contains("Upgrade") {
+                        // Switch protocols and echo whatever arrives
+                        let switched = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+                        stream.write_all(switched.as_bytes()).await.unwrap();
+                        let (mut reader, mut writer) = tokio::io::split(stream);
+                        let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                        return;
+                    }
+                    let mut body = Vec::new();
+                    let body_framing = BodyFraming::for_request(&headers).unwrap();
+                    framing::relay(&mut stream, &mut body, body_framing, None).await.unwrap();
+                    let path = head[0].split_whitespace().nth(1).unwrap_or("").to_string();
+                    let reply = format!("{} {}", path, body.len());
+                    let response = match path.as_str() {
+                        "/chunked" => format!(
+                            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                            reply.len(), reply
+                        ),
+                        "/both" => format!(
+                            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Length: 999\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                            reply.len(), reply
+                        ),
+                        _ => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", reply.len(), reply),
+                    };
+                    if stream.write_all(response.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+fn http_route(port: u16) -> Route {
+    Route {
+        name: "keepalive".to_string(),
+        target: Some(format!("127.0.0.1:{}", port)),
+        mode: "http".to_string(),
+        max_retries: 0,
+        ..Route::default()
+    }
+}
+
+// Send raw bytes through an HTTP route and read until porty closes the connection
+async fn exchange(route: Route, request: &[u8]) -> String {
+    let vhosts = VirtualHosts::new(vec![Arc::new(route)]).unwrap();
+    let tracker = ConnectionTracker::new();
+    let guard = tracker.track("keepalive", "127.0.0.1:5000");
+
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let serve = handle_http_connection(Box::new(server), guard.info(), &vhosts, false, false, LogFormat::Default, false);
+    let talk = async {
+        client.write_all(request).await.unwrap();
+        let mut received = String::new();
+        client.read_to_string(&mut received).await.unwrap();
+        received
+    };
+
+    let (_, received) = tokio::time::timeout(Duration::from_secs(5), async { tokio::join!(serve, talk) })
+        .await
+        .expect("porty should close the connection");
+    received
+}
+
+#[test]
+fn test_response_framing() {
+    let mut headers = Headers::new();
+    headers.append("Content-Length", "12");
+    assert_eq!(BodyFraming::for_response("GET", 200, &headers).unwrap(), BodyFraming::Length(12));
+    assert_eq!(BodyFraming::for_response("HEAD", 200, &headers).unwrap(), BodyFraming::Empty);
+    assert_eq!(BodyFraming::for_response("GET", 304, &headers).unwrap(), BodyFraming::Empty);
+
+    headers.append("Transfer-Encoding", "gzip, chunked");
+    assert_eq!(BodyFraming::for_response("GET", 200, &headers).unwrap(), BodyFraming::Chunked);
+    assert_eq!(BodyFraming::for_response("GET", 200, &Headers::new()).unwrap(), BodyFraming::UntilClose);
+
+    let mut conflicting = Headers::new();
+    conflicting.append("Content-Length", "5");
+    conflicting.append("Content-Length", "6");
+    assert!(framing::content_length(&conflicting).is_err());
+
+    println!("✅ Response framing follows RFC 9112");
+}
+
+#[test]
+fn test_request_framing() {
+    let mut headers = Headers::new();
+    assert_eq!(BodyFraming::for_request(&headers).unwrap(), BodyFraming::Empty);
+
+    headers.append("Transfer-Encoding", "chunked");
+    assert_eq!(BodyFraming::for_request(&headers).unwrap(), BodyFraming::Chunked);
+
+    // Smuggling ambiguity: both framings present
+    headers.append("Content-Length", "4");
+    assert!(BodyFraming::for_request(&headers).is_err());
+
+    let mut gzip = Headers::new();
+    gzip.append("Transfer-Encoding", "gzip");
+    assert!(BodyFraming::for_request(&gzip).is_err());
+
+    // Lengths are bare digits: no signs, blanks or inner whitespace
+    for bad in ["+5", "-5", "", "5 5", "0x5"] {
+        let mut signed = Headers::new();
+        signed.append("Content-Length", bad);
+        assert!(BodyFraming::for_request(&signed).is_err(), "Content-Length '{}'", bad);
+    }
+    let mut repeated = Headers::new();
+    repeated.append("Content-Length", "5, 5");
+    assert_eq!(BodyFraming::for_request(&repeated).unwrap(), BodyFraming::Length(5));
+
+    println!("✅ Request framing rejects ambiguous bodies");
+}
+
+#[tokio::test]
+async fn test_relay_rejects_malformed_chunk_sizes() {
+    for bad in [&b" +a\r\n"[..], b"+a\r\n", b"-1\r\n", b"\r\n", b"a b\r\n", b" a\r\n", b"0x a\r\n", b"a\n"] {
+        let mut wire = bad.to_vec();
+        wire.extend_from_slice(b"0123456789\r\n0\r\n\r\n");
+        let mut out = Vec::new();
+        let relayed = framing::relay(&mut BufReader::new(&wire[..]), &mut out, BodyFraming::Chunked, None).await;
+        assert!(relayed.is_err(), "chunk size {:?}", String::from_utf8_lossy(bad));
+    }
+
+    // Whitespace is only allowed ahead of an extension
+    let mut out = Vec::new();
+    let wire = b"a ;ext=1\r\n0123456789\r\n0\r\n\r\n";
+    framing::relay(&mut BufReader::new(&wire[..]), &mut out, BodyFraming::Chunked, None).await.unwrap();
+    assert_eq!(out, wire);
+
+    // Chunk data must be followed by exactly CRLF, and the body must end with one
+    for bad in [&b"a\r\n0123456789XY0\r\n\r\n"[..], b"a\r\n0123456789\n0\r\n\r\n", b"a\r\n0123456789\r\n0\r\n\n"] {
+        let mut out = Vec::new();
+        let relayed = framing::relay(&mut BufReader::new(bad), &mut out, BodyFraming::Chunked, None).await;
+        assert!(relayed.is_err(), "body {:?}", String::from_utf8_lossy(bad));
+        let relayed = framing::relay_dechunked(&mut BufReader::new(bad), &mut Vec::new(), None).await;
+        assert!(relayed.is_err(), "body {:?}", String::from_utf8_lossy(bad));
+    }
+
+    println!("✅ Chunk sizes are bare hex digits");
+}
+
+#[tokio::test]
+async fn test_relay_stops_at_end_of_chunked_body() {
+    let wire = b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nExpires: never\r\n\r\nGET /next HTTP/1.1\r\n";
+    let mut reader = BufReader::new(&wire[..]);
+    let mut out = Vec::new();
+
+    let copied = framing::relay(&mut reader, &mut out, BodyFraming::Chunked, None).await.unwrap();
+    assert_eq!(out, b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nExpires: never\r\n\r\n");
+    assert_eq!(copied, out.len() as u64);
+
+    // The next pipelined message is left unread
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).await.unwrap();
+    assert_eq!(rest, "GET /next HTTP/1.1\r\n");
+
+    println!("✅ Chunked bodies end at the last chunk");
+}
+
+#[tokio::test]
+async fn test_keep_alive_serves_pipelined_requests() {
+    let port = spawn_backend().await;
+
+    let received = exchange(
+        http_route(port),
+        b"GET /one HTTP/1.1\r\nHost: a\r\n\r\nGET /two HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert_eq!(
+        received,
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n/one 0\
+         HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\n/two 0"
+    );
+
+    println!("✅ Keep-alive connections serve pipelined requests in order");
+}
+
+#[tokio::test]
+async fn test_chunked_request_body_reaches_backend() {
+    let port = spawn_backend().await;
+
+    let received = exchange(
+        http_route(port),
+        b"POST /upload HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+          5\r\nhello\r\n6\r\n world\r\n0\r\nX-Checksum: 1\r\n\r\n\
+          GET /after HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    // The backend saw the chunked framing as sent, and the next request parsed cleanly
+    assert!(received.contains("/upload 41"), "{}", received);
+    assert!(received.ends_with("/after 0"), "{}", received);
+
+    let rejected = exchange(
+        http_route(port),
+        b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+    )
+    .await;
+    assert!(rejected.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", rejected);
+
+    println!("✅ Chunked request bodies stream to the backend");
+}
+
+#[tokio::test]
+async fn test_request_body_limit() {
+    // Chunked bodies are cut off at the chunk that crosses the limit
+    let wire = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+    let mut out = Vec::new();
+    let err = framing::relay_limited(&mut BufReader::new(&wire[..]), &mut out, BodyFraming::Chunked, 6, None)
+        .await
+        .unwrap_err();
+    assert_eq!(error_kind(&err), ErrorKind::TooLarge);
+    assert_eq!(out, b"4\r\nWiki\r\n");
+
+    let copied = framing::relay_limited(&mut BufReader::new(&wire[..]), &mut Vec::<u8>::new(), BodyFraming::Chunked, 9, None)
+        .await
+        .unwrap();
+    assert_eq!(copied, wire.len() as u64);
+
+    // Declared lengths over the limit get a 413 without reaching the backend
+    let port = spawn_backend().await;
+    let route = Route { max_request_body: Some(8), ..http_route(port) };
+    let received = exchange(
+        route,
+        b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 1048576\r\n\r\n",
+    )
+    .await;
+    assert!(received.starts_with("HTTP/1.1 413 Content Too Large\r\n"), "{}", received);
+
+    let route = Route { max_request_body: Some(8), ..http_route(port) };
+    let received = exchange(
+        route,
+        b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+    )
+    .await;
+    assert!(received.ends_with("/upload 5"), "{}", received);
+
+    println!("✅ Request bodies over max_request_body get a 413");
+}
+
+#[tokio::test]
+async fn test_upgrade_switches_to_tunnel() {
+    let port = spawn_backend().await;
+    let route = Route { upgrade_timeout_seconds: 1, ..http_route(port) };
+
+    // Bytes sent right behind the handshake travel through the tunnel, which
+    // closes once it has been idle for upgrade_timeout_seconds
+    let received = exchange(
+        route,
+        b"GET /ws HTTP/1.1\r\nHost: a\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\nping",
+    )
+    .await;
+    assert_eq!(
+        received,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\nping"
+    );
+
+    println!("✅ Upgrade requests become a bidirectional tunnel");
+}
+
+#[tokio::test]
+async fn test_stalled_body_times_out() {
+    // The client announces 1000 bytes, sends three and goes quiet
+    let (mut sender, receiver) = tokio::io::duplex(1024);
+    sender.write_all(b"hel").await.unwrap();
+    let mut out = Vec::new();
+    let idle = Some(Duration::from_millis(100));
+    let err = framing::relay(&mut BufReader::new(receiver), &mut out, BodyFraming::Length(1000), idle)
+        .await
+        .unwrap_err();
+    assert_eq!(error_kind(&err), ErrorKind::Timeout);
+    assert_eq!(out, b"hel");
+    drop(sender);
+
+    println!("✅ Stalled bodies time out");
+}
+
+#[tokio::test]
+async fn test_trickled_head_times_out() {
+    let port = spawn_backend().await;
+
+    // Sending the first bytes does not buy more time for the rest of the head
+    let route = Route { timeout_seconds: 1, ..http_route(port) };
+    let received = exchange(route, b"GET / HTTP/1.1\r\nHo").await;
+    assert!(received.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{}", received);
+
+    println!("✅ Slow request heads time out");
+}
+
+#[tokio::test]
+async fn test_http_10_clients_get_unchunked_bodies() {
+    let port = spawn_backend().await;
+
+    let received = exchange(http_route(port), b"GET /chunked HTTP/1.0\r\nHost: a\r\n\r\n").await;
+    assert!(received.contains("Connection: close\r\n"), "{}", received);
+    assert!(!received.contains("Transfer-Encoding"), "{}", received);
+    assert!(received.ends_with("\r\n\r\n/chunked 0"), "{}", received);
+
+    // HTTP/1.1 clients get the chunks as the backend sent them
+    let received = exchange(
+        http_route(port),
+        b"GET /chunked HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(received.ends_with("\r\n\r\na\r\n/chunked 0\r\n0\r\n\r\n"), "{}", received);
+
+    println!("✅ HTTP/1.0 clients get chunked bodies decoded");
+}
+
+#[tokio::test]
+async fn test_chunked_responses_drop_content_length() {
+    let port = spawn_backend().await;
+
+    let received = exchange(http_route(port), b"GET /both HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").await;
+    assert!(received.contains("Transfer-Encoding: chunked\r\n"), "{}", received);
+    assert!(!received.contains("Content-Length"), "{}", received);
+    assert!(received.ends_with("\r\n\r\n7\r\n/both 0\r\n0\r\n\r\n"), "{}", received);
+
+    println!("✅ Chunked responses reach the client without Content-Length");
+}
+
+#[tokio::test]
+async fn test_expect_continue() {
+    let port = spawn_backend().await;
+    let vhosts = VirtualHosts::new(vec![Arc::new(http_route(port))]).unwrap();
+    let tracker = ConnectionTracker::new();
+    let guard = tracker.track("keepalive", "127.0.0.1:5000");
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let serve = handle_http_connection(Box::new(server), guard.info(), &vhosts, false, false, LogFormat::Default, false);
+    let talk = async {
+        let mut client = BufReader::new(client);
+        client
+            .write_all(b"POST /upload HTTP/1.1\r\nHost: a\r\nExpect: 100-continue\r\nContent-Length: 5\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        // The body only goes out once porty says to continue
+        let interim = framing::read_head(&mut client).await.unwrap();
+        assert_eq!(interim, vec!["HTTP/1.1 100 Continue".to_string()]);
+        client.write_all(b"hello").await.unwrap();
+        let mut received = String::new();
+        client.read_to_string(&mut received).await.unwrap();
+        received
+    };
+
+    let (_, received) = tokio::time::timeout(Duration::from_secs(5), async { tokio::join!(serve, talk) })
+        .await
+        .expect("the client should not stall waiting for 100 Continue");
+    assert!(received.starts_with("HTTP/1.1 200 OK\r\n"), "{}", received);
+    assert!(received.ends_with("/upload 5"), "{}", received);
+
+    println!("✅ Expect: 100-continue gets an interim response");
+}
@@ -0,0 +1,130 @@
+// Backend pool selection tests
+
+use porty::balance::{BackendPool, BackendTarget, BalanceContext};
+use porty::Route;
+
+fn pool_route(balance: &str, targets: &[(&str, u32)]) -> Route {
+    Route {
+        name: "pool".to_string(),
+        listen_port: 8080,
+        targets: targets.iter()
+            .map(|(target, weight)| BackendTarget { target: target.to_string(), weight: *weight })
+            .collect(),
+        balance: balance.to_string(),
+        ..Route::default()
+    }
+}
+
+fn pick(pool: &BackendPool, ctx: &BalanceContext<'_>) -> String {
+    pool.select(ctx).unwrap().endpoint().to_string()
+}
+
+#[test]
+fn test_weighted_round_robin() {
+    let pool = BackendPool::from_route(&pool_route(
+        "round-robin",
+        &[("10.0.0.1:80", 2), ("10.0.0.2:80", 1), ("10.0.0.3:80", 0)],
+    )).unwrap();
+
+    let picks: Vec<String> = (0..6).map(|_| pick(&pool, &BalanceContext::default())).collect();
+    assert_eq!(picks.iter().filter(|p| *p == "10.0.0.1:80").count(), 4);
+    assert_eq!(picks.iter().filter(|p| *p == "10.0.0.2:80").count(), 2);
+    assert!(!picks.contains(&"10.0.0.3:80".to_string()));
+
+    println!("✅ Weighted round-robin works");
+}
+
+#[test]
+fn test_least_connections() {
+    let pool = BackendPool::from_route(&pool_route(
+        "least-connections",
+        &[("10.0.0.1:80", 1), ("10.0.0.2:80", 1)],
+    )).unwrap();
+
+    let first = pool.select(&BalanceContext::default()).unwrap();
+    let second = pool.select(&BalanceContext::default()).unwrap();
+    assert_ne!(first.endpoint(), second.endpoint());
+
+    // Releasing a lease makes its backend the least loaded again
+    let freed = first.endpoint().to_string();
+    drop(first);
+    assert_eq!(pick(&pool, &BalanceContext::default()), freed);
+
+    println!("✅ Least-connections tracks active leases");
+}
+
+#[test]
+fn test_consistent_hash() {
+    let targets = [("10.0.0.1:80", 1), ("10.0.0.2:80", 1), ("10.0.0.3:80", 1)];
+    let pool = BackendPool::from_route(&pool_route("consistent-hash", &targets)).unwrap();
+
+    let ctx = BalanceContext { client_ip: Some("192.168.1.20".parse().unwrap()), header_value: None };
+    let chosen = pick(&pool, &ctx);
+    for _ in 0..10 {
+        assert_eq!(pick(&pool, &ctx), chosen);
+    }
+
+    // Clients spread over every backend
+    let mut seen = std::collections::HashSet::new();
+    for i in 0..100 {
+        let ip = format!("10.1.0.{}", i).parse().unwrap();
+        seen.insert(pick(&pool, &BalanceContext { client_ip: Some(ip), header_value: None }));
+    }
+    assert_eq!(seen.len(), 3);
+
+    println!("✅ Consistent hashing is stable and spread out");
+}
+
+#[test]
+fn test_invalid_pool_config() {
+    assert!(BackendPool::from_route(&pool_route("fastest", &[("10.0.0.1:80", 1)])).is_err());
+    assert!(BackendPool::from_route(&pool_route("random", &[("10.0.0.1:80", 0)])).is_err());
+    assert!(BackendPool::from_route(&pool_route("consistent-hash", &[("10.0.0.1:80", 1001)])).is_err());
+    assert!(BackendPool::from_route(&pool_route("consistent-hash", &[("10.0.0.1:80", 1000)])).is_ok());
+    assert!(BackendPool::from_route(&pool_route("random", &[("no-port", 1)])).is_err());
+    // No target at all, rather than a backend at port 0
+    assert!(BackendPool::from_route(&pool_route("round-robin", &[])).is_err());
+    assert!(pool_route("round-robin", &[]).listen_endpoint("0.0.0.0").is_ok());
+    assert!(Route::default().listen_endpoint("0.0.0.0").is_err());
+
+    let mut route = pool_route("consistent-hash", &[("10.0.0.1:80", 1)]);
+    route.hash_key = "cookie:session".to_string();
+    assert!(BackendPool::from_route(&route).is_err());
+
+    println!("✅ Invalid pool settings are rejected");
+}
+
+#[test]
+fn test_unhealthy_backends_leave_rotation() {
+    let targets = [("10.0.0.1:80", 1), ("10.0.0.2:80", 1)];
+    let pool = BackendPool::from_route(&pool_route("consistent-hash", &targets)).unwrap();
+    let ctx = BalanceContext { client_ip: Some("192.168.1.20".parse().unwrap()), header_value: None };
+
+    // Keys of a down backend move to a healthy one
+    let chosen = pick(&pool, &ctx);
+    let down = pool.backends().iter().find(|b| b.endpoint.to_string() == chosen).unwrap();
+    down.set_healthy(false);
+    assert_ne!(pick(&pool, &ctx), chosen);
+
+    // Nothing to pick once every backend is down
+    for backend in pool.backends() {
+        backend.set_healthy(false);
+    }
+    assert!(pool.select(&ctx).is_none());
+
+    println!("✅ Unhealthy backends are skipped");
+}
+
+#[test]
+fn test_reload_keeps_backend_health() {
+    let old = BackendPool::from_route(&pool_route("round-robin", &[("10.0.0.1:80", 1), ("10.0.0.2:80", 1)])).unwrap();
+    old.backends()[0].set_healthy(false);
+
+    // Backends both configs share keep their health; new ones start healthy
+    let new = BackendPool::from_route(&pool_route("round-robin", &[("10.0.0.1:80", 1), ("10.0.0.3:80", 1)])).unwrap();
+    new.inherit_health(&old);
+    assert!(!new.backends()[0].is_healthy());
+    assert!(new.backends()[1].is_healthy());
+
+    println!("✅ Backend health survives a reload");
+}
@@ -0,0 +1,39 @@
+// Log file rotation tests
+
+use porty::logfile::{flush, set_rotation, LogFile, LogRotation};
+use std::fs;
+
+#[test]
+fn test_size_rotation_and_reopen() {
+    let dir = std::env::temp_dir().join(format!("porty-log-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("access.log");
+    let numbered = |n: usize| dir.join(format!("access.log.{}", n));
+
+    set_rotation(LogRotation { max_size_mb: 1, interval: "never".to_string(), keep: 2 });
+    let file = LogFile::open(path.to_str().unwrap()).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&file, &LogFile::open(path.to_str().unwrap()).unwrap()));
+
+    // 1 KiB lines: 3.5 MiB spills into two rotated files and drops the oldest
+    let line = "x".repeat(1023);
+    for _ in 0..3584 {
+        file.write_line(&line);
+    }
+    flush();
+    assert!(fs::metadata(&path).unwrap().len() <= 1024 * 1024);
+    assert_eq!(fs::metadata(numbered(1)).unwrap().len(), 1024 * 1024);
+    assert_eq!(fs::metadata(numbered(2)).unwrap().len(), 1024 * 1024);
+    assert!(!numbered(3).exists());
+
+    // After an external move, reopen starts a fresh file at the same path
+    fs::rename(&path, dir.join("moved.log")).unwrap();
+    file.reopen().unwrap();
+    file.write_line("after reopen");
+    flush();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "after reopen\n");
+
+    assert!(LogRotation { interval: "weekly".to_string(), ..LogRotation::default() }.validate().is_err());
+    fs::remove_dir_all(&dir).unwrap();
+
+    println!("✅ Log files rotate by size and reopen");
+}
@@ -0,0 +1,46 @@
+// Prometheus metrics rendering tests
+
+use porty::metrics;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[test]
+fn test_route_counters_render() {
+    let web = metrics::route("render-test");
+    web.bytes_sent.fetch_add(512, Ordering::Relaxed);
+    web.record_status(200);
+    web.record_status(204);
+    web.record_status(503);
+
+    let active = web.connection_opened();
+    let output = metrics::render();
+    assert!(output.contains("# TYPE porty_connections_total counter"));
+    assert!(output.contains("porty_connections_total{route=\"render-test\"} 1"));
+    assert!(output.contains("porty_active_connections{route=\"render-test\"} 1"));
+    assert!(output.contains("porty_bytes_sent_total{route=\"render-test\"} 512"));
+    assert!(output.contains("porty_http_requests_total{route=\"render-test\",class=\"2xx\"} 2"));
+    assert!(output.contains("porty_http_requests_total{route=\"render-test\",class=\"5xx\"} 1"));
+
+    drop(active);
+    assert!(metrics::render().contains("porty_active_connections{route=\"render-test\"} 0"));
+
+    println!("✅ Route counters render in Prometheus format");
+}
+
+#[test]
+fn test_histogram_buckets_are_cumulative() {
+    let route = metrics::route("histogram-test");
+    route.request_duration.observe(Duration::from_millis(3));
+    route.request_duration.observe(Duration::from_millis(80));
+    route.request_duration.observe(Duration::from_secs(30));
+
+    let output = metrics::render();
+    let name = "porty_http_request_duration_seconds";
+    assert!(output.contains(&format!("{}_bucket{{route=\"histogram-test\",le=\"0.005\"}} 1", name)));
+    assert!(output.contains(&format!("{}_bucket{{route=\"histogram-test\",le=\"0.1\"}} 2", name)));
+    assert!(output.contains(&format!("{}_bucket{{route=\"histogram-test\",le=\"10\"}} 2", name)));
+    assert!(output.contains(&format!("{}_bucket{{route=\"histogram-test\",le=\"+Inf\"}} 3", name)));
+    assert!(output.contains(&format!("{}_count{{route=\"histogram-test\"}} 3", name)));
+
+    println!("✅ Histogram buckets are cumulative");
+}
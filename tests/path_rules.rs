@@ -0,0 +1,43 @@
+// Path-prefix rule tests for HTTP routes
+
+use porty::http::{match_path_rule, PathRule};
+
+fn rule(prefix: &str, target: &str, strip_prefix: bool) -> PathRule {
+    PathRule {
+        prefix: prefix.to_string(),
+        target: target.to_string(),
+        strip_prefix,
+    }
+}
+
+#[test]
+fn test_longest_prefix_wins() {
+    let rules = vec![
+        rule("/api/*", "127.0.0.1:3000", false),
+        rule("/api/v2", "127.0.0.1:3002", false),
+        rule("/static/", "127.0.0.1:4000", false),
+    ];
+
+    let target = |path: &str| match_path_rule(&rules, path).map(|r| r.target.as_str());
+    assert_eq!(target("/api/users"), Some("127.0.0.1:3000"));
+    assert_eq!(target("/api/v2/users"), Some("127.0.0.1:3002"));
+    assert_eq!(target("/api"), Some("127.0.0.1:3000"));
+    assert_eq!(target("/static/app.js"), Some("127.0.0.1:4000"));
+    // Prefixes match whole segments only
+    assert_eq!(target("/apix"), None);
+    assert_eq!(target("/"), None);
+
+    println!("✅ Longest-prefix path matching works");
+}
+
+#[test]
+fn test_strip_prefix() {
+    let stripped = rule("/api/*", "127.0.0.1:3000", true);
+    assert_eq!(stripped.rewrite("/api/users"), "/users");
+    assert_eq!(stripped.rewrite("/api"), "/");
+
+    let kept = rule("/api", "127.0.0.1:3000", false);
+    assert_eq!(kept.rewrite("/api/users"), "/api/users");
+
+    println!("✅ Prefix stripping works");
+}
@@ -0,0 +1,85 @@
+// PROXY protocol header tests
+
+use porty::acl::{trusts_proxy_header, Cidr};
+use porty::proxy_protocol::{encode, read_header, ProxyAddresses, ProxyVersion};
+use porty::Route;
+use tokio::io::AsyncReadExt;
+
+fn addresses(source: &str, destination: &str) -> ProxyAddresses {
+    ProxyAddresses {
+        source: Some(source.parse().unwrap()),
+        destination: Some(destination.parse().unwrap()),
+    }
+}
+
+#[test]
+fn test_v1_encoding() {
+    let ipv4 = addresses("203.0.113.7:51000", "10.0.0.5:5432");
+    assert_eq!(encode(ProxyVersion::V1, &ipv4), b"PROXY TCP4 203.0.113.7 10.0.0.5 51000 5432\r\n");
+
+    // Mixed families are sent as IPv6
+    let mixed = addresses("203.0.113.7:51000", "[2001:db8::5]:5432");
+    assert_eq!(
+        encode(ProxyVersion::V1, &mixed),
+        b"PROXY TCP6 ::ffff:203.0.113.7 2001:db8::5 51000 5432\r\n"
+    );
+
+    assert_eq!(encode(ProxyVersion::V1, &ProxyAddresses::default()), b"PROXY UNKNOWN\r\n");
+
+    let version: ProxyVersion = serde_json::from_str("\"v2\"").unwrap();
+    assert_eq!(version, ProxyVersion::V2);
+
+    println!("✅ PROXY v1 headers encode");
+}
+
+#[tokio::test]
+async fn test_headers_round_trip_without_over_reading() {
+    for version in [ProxyVersion::V1, ProxyVersion::V2] {
+        for sent in [addresses("203.0.113.7:51000", "10.0.0.5:25"), addresses("[2001:db8::7]:51000", "[2001:db8::5]:25")] {
+            let mut wire = encode(version, &sent);
+            wire.extend_from_slice(b"EHLO client\r\n");
+            let mut stream = &wire[..];
+
+            let received = read_header(&mut stream).await.unwrap();
+            assert_eq!(received, Some(sent), "{:?}", version);
+
+            let mut rest = String::new();
+            stream.read_to_string(&mut rest).await.unwrap();
+            assert_eq!(rest, "EHLO client\r\n");
+        }
+    }
+
+    // LOCAL and UNKNOWN keep the socket's own address
+    let local = encode(ProxyVersion::V2, &ProxyAddresses::default());
+    assert_eq!(read_header(&mut &local[..]).await.unwrap(), None);
+    assert_eq!(read_header(&mut &b"PROXY UNKNOWN\r\n"[..]).await.unwrap(), None);
+
+    println!("✅ PROXY headers round-trip and leave the payload unread");
+}
+
+#[tokio::test]
+async fn test_invalid_headers_are_refused() {
+    assert!(read_header(&mut &b"GET / HTTP/1.1\r\n\r\n"[..]).await.is_err());
+    assert!(read_header(&mut &b"PROXY TCP4 nonsense\r\n"[..]).await.is_err());
+    assert!(read_header(&mut &b"PROXY TCP4 2001:db8::7 10.0.0.5 51000 25\r\n"[..]).await.is_err());
+    assert!(read_header(&mut &b"PROXY TCP6 203.0.113.7 2001:db8::5 51000 25\r\n"[..]).await.is_err());
+    assert!(read_header(&mut &[b'P'; 200][..]).await.is_err());
+
+    println!("✅ Malformed PROXY headers are refused");
+}
+
+#[test]
+fn test_only_listed_peers_send_headers() {
+    let route = Route {
+        accept_proxy_protocol: true,
+        proxy_protocol_from: vec![Cidr::parse("10.0.0.0/8").unwrap()],
+        ..Route::default()
+    };
+    assert!(trusts_proxy_header(&route, "10.1.2.3:40000"));
+    assert!(!trusts_proxy_header(&route, "203.0.113.7:51000"));
+    assert!(!trusts_proxy_header(&Route::default(), "10.1.2.3:40000"));
+    // Unix socket peers are local
+    assert!(trusts_proxy_header(&route, "unix:(unnamed)"));
+
+    println!("✅ PROXY headers are only taken from proxy_protocol_from");
+}
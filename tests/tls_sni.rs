@@ -0,0 +1,68 @@
+// SNI extraction tests for tls-passthrough routing
+
+use porty::tls::{parse_sni, read_client_hello};
+
+// Minimal ClientHello body carrying the given server_name extension
+fn client_hello_body(server_name: Option<&str>) -> Vec<u8> {
+    let mut body = vec![0x03, 0x03];          // legacy_version
+    body.extend_from_slice(&[0u8; 32]);       // random
+    body.push(0);                             // session_id
+    body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // one cipher suite
+    body.extend_from_slice(&[0x01, 0x00]);    // null compression
+
+    let mut extensions = Vec::new();
+    // An unrelated extension first (supported_versions)
+    extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+    if let Some(name) = server_name {
+        let name = name.as_bytes();
+        let list_len = name.len() + 3;
+        extensions.extend_from_slice(&[0x00, 0x00]);
+        extensions.extend_from_slice(&((list_len + 2) as u16).to_be_bytes());
+        extensions.extend_from_slice(&(list_len as u16).to_be_bytes());
+        extensions.push(0);
+        extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(name);
+    }
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(&extensions);
+    body
+}
+
+fn tls_record(body: &[u8]) -> Vec<u8> {
+    let mut handshake = vec![0x01];
+    handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(body);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+#[test]
+fn test_parse_sni() {
+    assert_eq!(parse_sni(&client_hello_body(Some("API.Example.com"))), Some("api.example.com".to_string()));
+    assert_eq!(parse_sni(&client_hello_body(None)), None);
+
+    // Truncated input must not panic
+    let body = client_hello_body(Some("api.example.com"));
+    assert_eq!(parse_sni(&body[..body.len() - 4]), None);
+
+    println!("✅ SNI parsing works");
+}
+
+#[tokio::test]
+async fn test_read_client_hello_returns_raw_bytes() {
+    let record = tls_record(&client_hello_body(Some("db.internal")));
+    let mut stream = &record[..];
+
+    let (raw, server_name) = read_client_hello(&mut stream).await.unwrap();
+    assert_eq!(raw, record);
+    assert_eq!(server_name.as_deref(), Some("db.internal"));
+
+    // Plain HTTP is rejected rather than forwarded to a TLS backend
+    let mut plaintext = &b"GET / HTTP/1.1\r\n\r\n"[..];
+    assert!(read_client_hello(&mut plaintext).await.is_err());
+
+    println!("✅ ClientHello capture works");
+}
@@ -0,0 +1,79 @@
+// Virtual host selection tests for HTTP routes sharing a listener
+
+use porty::vhost::{normalize_host, HostList, VirtualHosts};
+use porty::Route;
+use std::sync::Arc;
+
+fn http_route(name: &str, host: Option<HostList>) -> Arc<Route> {
+    Arc::new(Route {
+        name: name.to_string(),
+        listen_port: 8080,
+        target_addr: "127.0.0.1".to_string(),
+        target_port: 3000,
+        mode: "http".to_string(),
+        host,
+        ..Route::default()
+    })
+}
+
+fn selected(vhosts: &VirtualHosts, host: &str) -> Option<String> {
+    vhosts.select(Some(host)).map(|m| m.route.name.clone())
+}
+
+#[test]
+fn test_normalize_host() {
+    assert_eq!(normalize_host("API.Example.com:8080"), "api.example.com");
+    assert_eq!(normalize_host("example.com."), "example.com");
+    assert_eq!(normalize_host("[::1]:8080"), "::1");
+
+    println!("✅ Host normalization works");
+}
+
+#[test]
+fn test_host_precedence() {
+    let vhosts = VirtualHosts::new(vec![
+        http_route("regex", Some(HostList::One(r"~pr-\d+\.(dev|qa)\.local".to_string()))),
+        http_route("wildcard", Some(HostList::One("*.dev.local".to_string()))),
+        http_route("nested", Some(HostList::One("*.api.dev.local".to_string()))),
+        http_route("exact", Some(HostList::Many(vec!["app.dev.local".to_string(), "www.dev.local".to_string()]))),
+    ]).unwrap();
+
+    assert_eq!(selected(&vhosts, "APP.dev.local:8080").as_deref(), Some("exact"));
+    assert_eq!(selected(&vhosts, "www.dev.local").as_deref(), Some("exact"));
+    assert_eq!(selected(&vhosts, "feature-x.dev.local").as_deref(), Some("wildcard"));
+    assert_eq!(selected(&vhosts, "v2.api.dev.local").as_deref(), Some("nested"));
+    // Wildcards outrank regexes, so the regex only sees hosts no wildcard covers
+    assert_eq!(selected(&vhosts, "pr-42.dev.local").as_deref(), Some("wildcard"));
+    // Wildcards cover exactly one label, not the bare parent domain or deeper names
+    assert_eq!(selected(&vhosts, "dev.local"), None);
+    assert_eq!(selected(&vhosts, "a.b.dev.local"), None);
+    // Regexes match the whole host
+    assert_eq!(selected(&vhosts, "pr-7.qa.local").as_deref(), Some("regex"));
+    assert_eq!(selected(&vhosts, "pr-7.qa.local.evil.com"), None);
+    assert_eq!(selected(&vhosts, "xpr-7.qa.local"), None);
+
+    println!("✅ Host precedence works");
+}
+
+#[test]
+fn test_default_route() {
+    let mut fallback = (*http_route("fallback", Some(HostList::One("a.test".to_string())))).clone();
+    fallback.default = true;
+
+    let vhosts = VirtualHosts::new(vec![
+        http_route("b", Some(HostList::One("b.test".to_string()))),
+        Arc::new(fallback),
+    ]).unwrap();
+
+    let matched = vhosts.select(Some("unknown.test")).unwrap();
+    assert_eq!(matched.route.name, "fallback");
+    assert!(!matched.host_matched);
+
+    // Duplicate exact hosts are a configuration error
+    assert!(VirtualHosts::new(vec![
+        http_route("one", Some(HostList::One("dup.test".to_string()))),
+        http_route("two", Some(HostList::One("DUP.test".to_string()))),
+    ]).is_err());
+
+    println!("✅ Default route selection works");
+}